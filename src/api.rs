@@ -0,0 +1,176 @@
+use cargo::{sources::RegistrySource, util::config::Config as CargoConfig};
+use crates_io::NewCrate;
+use log::debug;
+use reqwest::{blocking::RequestBuilder, header::CONTENT_TYPE, Method};
+use serde::Deserialize;
+
+use crate::config::Header;
+
+/// Sends requests to a registry's web API
+pub struct RegistryApi {
+    client: reqwest::blocking::Client,
+    api_url: String,
+    headers: Vec<Header>,
+}
+
+impl RegistryApi {
+    /// Creates a client for the `api` url in the registry's `config.json` that adds the headers to each request.
+    /// The registry index should already be updated.
+    pub fn new(
+        config: &CargoConfig,
+        registry: &mut RegistrySource,
+        registry_name: &str,
+        headers: Vec<Header>,
+    ) -> anyhow::Result<Self> {
+        let _package_cache_lock = config.acquire_package_cache_lock()?;
+        let api_url = match registry
+            .config()?
+            .and_then(|registry_config| registry_config.api)
+        {
+            Some(api_url) => api_url,
+            None => anyhow::bail!(
+                "The registry {} doesn't have an `api` url in its config.json",
+                registry_name
+            ),
+        };
+
+        Ok(Self {
+            client: reqwest::blocking::Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            headers,
+        })
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// Uploads a packaged crate along with its metadata. Returns the warnings from the registry.
+    pub fn publish(&self, new_crate: &NewCrate, tarball: &[u8]) -> anyhow::Result<Vec<String>> {
+        let json = serde_json::to_vec(new_crate)?;
+
+        // The body is the length of the metadata as a little-endian u32, the metadata,
+        // then the length of the `.crate` file and the file itself
+        let mut body = Vec::with_capacity(json.len() + tarball.len() + 8);
+        body.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        body.extend_from_slice(&json);
+        body.extend_from_slice(&(tarball.len() as u32).to_le_bytes());
+        body.extend_from_slice(tarball);
+
+        let response = self.send(self.request(Method::PUT, "/api/v1/crates/new").body(body))?;
+
+        let mut warnings = Vec::new();
+        for kind in &["invalid_categories", "invalid_badges", "other"] {
+            if let Some(values) = response["warnings"][kind].as_array() {
+                warnings.extend(
+                    values
+                        .iter()
+                        .filter_map(|value| value.as_str())
+                        .map(|value| match *kind {
+                            "invalid_categories" => format!("invalid category: {}", value),
+                            "invalid_badges" => format!("invalid badge: {}", value),
+                            _ => value.to_string(),
+                        }),
+                );
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    /// Yanks a version so that it isn't used for new lockfiles, or undoes it
+    pub fn yank(&self, name: &str, version: &str, undo: bool) -> anyhow::Result<()> {
+        let request_builder = if undo {
+            self.request(
+                Method::PUT,
+                &format!("/api/v1/crates/{}/{}/unyank", name, version),
+            )
+        } else {
+            self.request(
+                Method::DELETE,
+                &format!("/api/v1/crates/{}/{}/yank", name, version),
+            )
+        };
+        self.send(request_builder)?;
+
+        Ok(())
+    }
+
+    pub fn list_owners(&self, name: &str) -> anyhow::Result<Vec<Owner>> {
+        let response =
+            self.send(self.request(Method::GET, &format!("/api/v1/crates/{}/owners", name)))?;
+
+        let users = response["users"].as_array().cloned().unwrap_or_default();
+        Ok(users
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Adds or removes owners of a crate by their logins. Returns the registry's message, if it sent one.
+    pub fn change_owners(
+        &self,
+        name: &str,
+        logins: &[String],
+        remove: bool,
+    ) -> anyhow::Result<Option<String>> {
+        let method = if remove { Method::DELETE } else { Method::PUT };
+        let body = serde_json::json!({ "users": logins });
+        let response = self.send(
+            self.request(method, &format!("/api/v1/crates/{}/owners", name))
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string()),
+        )?;
+
+        Ok(response["msg"].as_str().map(String::from))
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}{}", self.api_url, path);
+        debug!("{} {}", method, url);
+
+        let mut request_builder = self.client.request(method, &url);
+        for header in &self.headers {
+            request_builder = request_builder.header(&header.name, &header.value);
+        }
+        request_builder
+    }
+
+    /// Sends the request and returns the JSON response.
+    /// The registry reports errors in an `errors` list, which can come with a successful status code.
+    fn send(&self, request_builder: RequestBuilder) -> anyhow::Result<serde_json::Value> {
+        let request = request_builder.build()?;
+        let url = request.url().clone();
+
+        let response = self.client.execute(request)?;
+        debug!("{:#?}", response);
+        let status = response.status();
+        let text = response.text()?;
+        debug!("BODY");
+        debug!("{}", text);
+
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+        if let Some(errors) = json["errors"].as_array() {
+            let details: Vec<&str> = errors
+                .iter()
+                .filter_map(|error| error["detail"].as_str())
+                .collect();
+            if !details.is_empty() {
+                anyhow::bail!("The registry returned an error: {}", details.join(", "));
+            }
+        }
+
+        if !status.is_success() {
+            anyhow::bail!("Request to {} failed with status {}", url, status);
+        }
+
+        Ok(json)
+    }
+}
+
+/// A user or team that owns a crate
+#[derive(Debug, Clone, Deserialize)]
+pub struct Owner {
+    pub login: String,
+    pub name: Option<String>,
+}
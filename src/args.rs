@@ -1,18 +1,46 @@
 use clap::Clap;
-use std::path::PathBuf;
+use semver::{Version, VersionReq};
+use std::{path::PathBuf, str::FromStr};
 
-use crate::config::{Config, Header};
+use crate::{
+    config::{Config, Header, RegistryConfig},
+    manifest::parse_rust_version,
+};
 
 #[derive(Clap, Debug, Clone)]
 #[clap(about, version)]
 pub enum CargoSideloadArgs {
     /// Downloads all packages in your `Cargo.toml` and places them in the local Cargo cache, limited to the specified registry.
+    /// If crates are specified, only those crates will be downloaded and no `Cargo.toml` is required.
     Fetch(CargoSideloadFetchArgs),
-    /// List some info for all published versions of the specified crate. Does not include yanked versions.
+    /// List some info for all published versions of the specified crate. Yanked versions are only included with `--include-yanked`.
     List(CargoSideloadListArgs),
+    /// Show the features and dependencies of a published version of the specified crate. Defaults to the latest version.
+    Info(CargoSideloadInfoArgs),
     /// List all crates in your `Cargo.toml` that have newer versions available, limited to the specified registry.
     Outdated(CargoSideloadOutdatedArgs),
+    /// Search the registry for crates by name and show their latest versions. Yanked versions are not included.
+    Search(CargoSideloadSearchArgs),
+    /// Show the changes between two published versions of the specified crate, starting with a summary of
+    /// the dependencies and features that changed in its `Cargo.toml`.
+    Diff(CargoSideloadDiffArgs),
+    /// Download a version of the specified crate, verify its checksum and extract its source into a directory.
+    Download(CargoSideloadDownloadArgs),
+    /// Manage the crates from the registry in the local Cargo cache
+    Cache(CargoSideloadCacheArgs),
+    /// Check the cached crates from the registry against the checksums in the registry index and `Cargo.lock`,
+    /// and check that their unpacked sources haven't been modified.
+    Verify(CargoSideloadVerifyArgs),
+    /// Run a local registry for development and testing
+    Registry(CargoSideloadRegistryArgs),
+    /// Package a crate and upload it to the registry, sending the same headers as `fetch`
+    Publish(CargoSideloadPublishArgs),
+    /// Yank a version of a crate from the registry, or undo a yank with `--undo`
+    Yank(CargoSideloadYankArgs),
+    /// List, add or remove the owners of a crate in the registry
+    Owner(CargoSideloadOwnerArgs),
 }
+
 #[derive(Clap, Debug, Clone)]
 pub struct CargoSideloadCommonArgs {
     #[clap(short = 'r', long = "registry", env = "CARGO_SIDELOAD_REGISTRY")]
@@ -21,25 +49,81 @@ pub struct CargoSideloadCommonArgs {
     #[clap(long = "path", default_value = ".")]
     /// Path to the `Cargo.toml` file of the crate you're running this command on.
     pub path: PathBuf,
+    #[clap(long = "manifest-path")]
+    /// Path to `Cargo.toml`. Takes precedence over `--path`.
+    pub manifest_path: Option<PathBuf>,
     #[clap(short = 'p', long = "packages")]
-    /// List of crates to run this command on.
+    /// List of crates from the registry to run this command on. Use `--member` to select workspace members instead.
     pub packages: Option<Vec<String>>,
+    #[clap(long)]
+    /// Only include the dependencies of all workspace members. This is the default when running from the workspace root.
+    pub workspace: bool,
+    #[clap(long = "member", conflicts_with = "workspace")]
+    /// Only include the dependencies of the specified workspace members.
+    /// Unlike `cargo`'s `-p`, this selects members, while `-p/--packages` filters the crates from the registry.
+    pub members: Vec<String>,
     #[clap(short, long)]
     /// Silence Cargo
     pub quiet: bool,
+    #[clap(long)]
+    /// Require `Cargo.lock` to be up to date. Returns an error instead of updating a missing or stale `Cargo.lock`.
+    pub locked: bool,
+    #[clap(long)]
+    /// Same as `--locked`, but also prevents Cargo from updating registry indexes.
+    pub frozen: bool,
+    #[clap(long = "no-write-lockfile", conflicts_with_all = &["locked", "frozen"])]
+    /// Resolve dependencies in memory without writing `Cargo.lock`. An existing `Cargo.lock` is used as a guide.
+    pub no_write_lockfile: bool,
 }
 
 #[derive(Clap, Debug, Clone)]
 pub struct CargoSideloadFetchArgs {
     #[clap(flatten)]
     pub common: CargoSideloadCommonArgs,
+    /// Crates to download in the format `[crate-name]@[version requirement]`.
+    /// The latest version is used if no requirement is given.
+    pub crates: Vec<CrateSpec>,
+    #[clap(long)]
+    /// Also download every dependency of the specified crates that is published to the same registry.
+    /// Dependencies are resolved through the registry index for the selected features and target.
+    pub deps: bool,
+    #[clap(flatten)]
+    pub resolve: CargoSideloadResolveArgs,
     #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
     /// Headers to add to the download request in the format `[Header-Name]: [Header Value]`.
     /// Note that only one header can be set by environment variable.
     pub headers: Vec<Header>,
     #[clap(short, long)]
-    /// Deletes any existing `.crate` file before downloading its replacement.
+    /// Deletes any existing `.crate` file and its unpacked source before downloading its replacement.
     pub force: bool,
+    #[clap(long = "dry-run", alias = "plan")]
+    /// Prints the packages that would be downloaded, along with their urls and headers, without downloading anything
+    /// or writing `Cargo.lock`.
+    pub dry_run: bool,
+    #[clap(long = "require-signatures")]
+    /// Fail if any package, including ones that are already cached, doesn't have a valid signature from one of the
+    /// registry's `trusted_keys`.
+    pub require_signatures: bool,
+    #[clap(skip)]
+    /// The registry's settings from the cargo-sideload config file
+    pub registry_config: RegistryConfig,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadResolveArgs {
+    #[clap(long)]
+    /// Space or comma separated list of features to activate.
+    /// Use `[crate-name]/[feature]` to only activate a feature for one crate.
+    pub features: Vec<String>,
+    #[clap(long = "no-default-features")]
+    /// Do not activate the `default` feature
+    pub no_default_features: bool,
+    #[clap(long)]
+    /// Target triple to resolve platform-specific dependencies for. Defaults to every platform.
+    pub target: Option<String>,
+    #[clap(long = "no-dev-deps")]
+    /// Do not include the dev-dependencies of workspace members
+    pub no_dev_deps: bool,
 }
 
 #[derive(Clap, Debug, Clone)]
@@ -55,6 +139,272 @@ pub struct CargoSideloadListArgs {
     #[clap(short, long = "version-only")]
     /// Only return version numbers
     pub version_only: bool,
+    #[clap(long = "include-prerelease")]
+    /// Include pre-release versions when looking for the latest version
+    pub include_prerelease: bool,
+    #[clap(long = "rust-version", parse(try_from_str = parse_rust_version))]
+    /// Skip versions that need a newer Rust version when looking for the latest version.
+    /// Defaults to the `rust-version` of the package in the current directory.
+    pub rust_version: Option<Version>,
+    #[clap(long = "include-yanked", conflicts_with = "latest")]
+    /// Include yanked versions and mark each version as yanked or live
+    pub include_yanked: bool,
+    #[clap(long)]
+    /// Only list versions that match this version requirement
+    pub req: Option<VersionReq>,
+    #[clap(long)]
+    /// Only list this version and higher version numbers. Versions are compared by number, not by when they were published.
+    pub since: Option<Version>,
+    #[clap(long, conflicts_with = "latest")]
+    /// Only list this many of the newest versions
+    pub limit: Option<usize>,
+    #[clap(short, long)]
+    /// Silence Cargo
+    pub quiet: bool,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadInfoArgs {
+    #[clap(name = "crate")]
+    /// Crate to show in the format `[crate-name]@[version requirement]`. The version requirement is optional.
+    /// A yanked version is only shown if no other version matches.
+    pub crate_spec: CrateSpec,
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(short, long)]
+    /// Silence Cargo
+    pub quiet: bool,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadSearchArgs {
+    /// Text to search for in crate names. Matches any part of the name unless `--prefix` or `--regex` is used.
+    pub pattern: String,
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(long)]
+    /// Maximum number of crates to show
+    pub limit: Option<usize>,
+    #[clap(long, conflicts_with = "prefix")]
+    /// Treat the pattern as a regular expression
+    pub regex: bool,
+    #[clap(long)]
+    /// Only match crate names that start with the pattern
+    pub prefix: bool,
+    #[clap(short, long)]
+    /// Silence Cargo
+    pub quiet: bool,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadDiffArgs {
+    /// Name of the crate to compare
+    pub name: String,
+    /// Version to compare from
+    pub from: Version,
+    /// Version to compare to
+    pub to: Version,
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
+    /// Headers to add to the download request in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
+    #[clap(short, long)]
+    /// Silence Cargo
+    pub quiet: bool,
+    #[clap(skip)]
+    /// The registry's settings from the cargo-sideload config file
+    pub registry_config: RegistryConfig,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadDownloadArgs {
+    #[clap(name = "crate")]
+    /// Crate to download in the format `[crate-name]@[version requirement]`. Defaults to the latest version.
+    pub crate_spec: CrateSpec,
+    #[clap(long)]
+    /// Directory to extract the source into. Defaults to `[crate-name]-[version]` in the current directory.
+    /// The directory must be empty if it already exists.
+    pub extract: Option<PathBuf>,
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
+    /// Headers to add to the download request in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
+    #[clap(short, long)]
+    /// Download the crate again even if it's already cached
+    pub force: bool,
+    #[clap(short, long)]
+    /// Silence Cargo
+    pub quiet: bool,
+    #[clap(skip)]
+    /// The registry's settings from the cargo-sideload config file
+    pub registry_config: RegistryConfig,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadCacheArgs {
+    #[clap(subcommand)]
+    pub command: CargoSideloadCacheCommand,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub enum CargoSideloadCacheCommand {
+    /// List the cached versions of each crate and their sizes
+    List(CargoSideloadCacheListArgs),
+    /// Remove cached versions that aren't used by any of the lockfiles or are older than a number of days
+    Prune(CargoSideloadCachePruneArgs),
+    /// Remove the cached versions of a crate, including the `.crate` file and the unpacked source
+    Remove(CargoSideloadCacheRemoveArgs),
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadCacheListArgs {
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadCachePruneArgs {
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(long = "lockfile")]
+    /// Keep the versions used by this `Cargo.lock` file, or the `Cargo.lock` file in this directory.
+    /// Can be used more than once.
+    pub lockfiles: Vec<PathBuf>,
+    #[clap(long = "older-than")]
+    /// Only remove versions that were downloaded more than this many days ago
+    pub older_than: Option<u64>,
+    #[clap(long = "dry-run")]
+    /// Print the versions that would be removed without removing them
+    pub dry_run: bool,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadCacheRemoveArgs {
+    #[clap(name = "crate")]
+    /// Crate to remove in the format `[crate-name]@[version requirement]`. Every cached version is removed if there's no version requirement.
+    pub crate_spec: CrateSpec,
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadVerifyArgs {
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(long = "lockfile")]
+    /// Check the checksums in this `Cargo.lock` file, or the `Cargo.lock` file in this directory.
+    /// Can be used more than once. Defaults to `Cargo.lock` in the current directory if it exists.
+    pub lockfiles: Vec<PathBuf>,
+    #[clap(long)]
+    /// Download the crates that don't match again
+    pub redownload: bool,
+    #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
+    /// Headers to add to the download request in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
+    #[clap(short, long)]
+    /// Silence Cargo
+    pub quiet: bool,
+    #[clap(skip)]
+    /// The registry's settings from the cargo-sideload config file
+    pub registry_config: RegistryConfig,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadRegistryArgs {
+    #[clap(subcommand)]
+    pub command: CargoSideloadRegistryCommand,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub enum CargoSideloadRegistryCommand {
+    /// Serve a registry from a directory. The index is kept in `index` and the `.crate` files in `crates`.
+    /// `.crate` files that aren't in the index yet are added to it when the server starts.
+    Serve(CargoSideloadRegistryServeArgs),
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadRegistryServeArgs {
+    #[clap(long)]
+    /// Directory of the registry. It's created if it doesn't exist.
+    pub dir: PathBuf,
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    /// Address to listen on
+    pub address: String,
+    #[clap(long)]
+    /// Headers that download requests must have in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
+    #[clap(long, default_value = "10485760")]
+    /// Largest upload that's accepted, in bytes. Defaults to 10 MiB, the same limit as crates.io.
+    pub max_upload_size: usize,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadPublishArgs {
+    #[clap(flatten)]
+    pub common: CargoSideloadCommonArgs,
+    #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
+    /// Headers to add to the upload request in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
+    #[clap(long = "allow-dirty")]
+    /// Allow publishing with uncommitted changes
+    pub allow_dirty: bool,
+    #[clap(long = "no-verify")]
+    /// Don't build the packaged crate before uploading it
+    pub no_verify: bool,
+    #[clap(long = "dry-run")]
+    /// Package the crate and print where it would be uploaded, without uploading it
+    pub dry_run: bool,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadYankArgs {
+    #[clap(name = "crate")]
+    /// Crate to yank in the format `[crate-name]@[version]`. The version requirement has to match a single version.
+    pub crate_spec: CrateSpec,
+    #[clap(long)]
+    /// Undo a yank, so the version can be used for new lockfiles again
+    pub undo: bool,
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
+    /// Headers to add to the request in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
+    #[clap(short, long)]
+    /// Silence Cargo
+    pub quiet: bool,
+}
+
+#[derive(Clap, Debug, Clone)]
+pub struct CargoSideloadOwnerArgs {
+    #[clap(name = "crate")]
+    /// Name of the crate
+    pub name: String,
+    #[clap(long)]
+    /// Logins of the users or teams to add as owners
+    pub add: Vec<String>,
+    #[clap(long)]
+    /// Logins of the users or teams to remove as owners
+    pub remove: Vec<String>,
+    #[clap(long)]
+    /// List the owners of the crate. This is done after any owners are added or removed.
+    pub list: bool,
+    #[clap(short, long, env = "CARGO_SIDELOAD_REGISTRY")]
+    /// Name of the registry as it is defined in your cargo config (usually `~/.cargo/config.toml`).
+    pub registry: String,
+    #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
+    /// Headers to add to the requests in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
     #[clap(short, long)]
     /// Silence Cargo
     pub quiet: bool,
@@ -64,9 +414,110 @@ pub struct CargoSideloadListArgs {
 pub struct CargoSideloadOutdatedArgs {
     #[clap(flatten)]
     pub common: CargoSideloadCommonArgs,
+    #[clap(flatten)]
+    pub resolve: CargoSideloadResolveArgs,
     #[clap(short, long)]
     /// Returns an error if any dependencies are out of date
     pub error: bool,
+    #[clap(long = "compatible-only")]
+    /// Only list dependencies with newer versions that are allowed by their version requirements
+    pub compatible_only: bool,
+    #[clap(long = "include-prerelease")]
+    /// Include pre-release versions when looking for the latest version.
+    /// Newer pre-releases of a pre-release version that's already in use are always included.
+    pub include_prerelease: bool,
+    #[clap(long = "rust-version", parse(try_from_str = parse_rust_version))]
+    /// Don't suggest versions that need a newer Rust version. Defaults to the lowest `rust-version` of the selected workspace members.
+    pub rust_version: Option<Version>,
+    #[clap(long = "direct-only", conflicts_with = "depth")]
+    /// Only check the direct dependencies of the workspace members
+    pub direct_only: bool,
+    #[clap(long)]
+    /// Only check dependencies up to this many levels away from the workspace members. Direct dependencies are at depth 1.
+    pub depth: Option<usize>,
+    #[clap(long, conflicts_with_all = &["locked", "frozen"])]
+    /// Rewrite the version requirements of outdated dependencies in each workspace member's `Cargo.toml`, then update `Cargo.lock`.
+    /// Requirements are updated to the compatible versions unless `--latest` is used.
+    pub update: bool,
+    #[clap(long, requires = "update", conflicts_with = "latest")]
+    /// Update requirements to the newest versions they already allow. This is the default for `--update`.
+    pub compatible: bool,
+    #[clap(long, requires = "update")]
+    /// Update requirements to the latest versions, including breaking changes
+    pub latest: bool,
+    #[clap(long, requires = "update")]
+    /// Download the updated packages after updating `Cargo.lock`
+    pub fetch: bool,
+    #[clap(long, env = "CARGO_SIDELOAD_HEADER", hide_env_values = true)]
+    /// Headers to add to the download request for `--fetch` in the format `[Header-Name]: [Header Value]`.
+    pub headers: Vec<Header>,
+    #[clap(long = "require-signatures", requires = "fetch")]
+    /// Fail `--fetch` if any package doesn't have a valid signature from one of the registry's `trusted_keys`.
+    pub require_signatures: bool,
+    #[clap(skip)]
+    /// The registry's settings from the cargo-sideload config file
+    pub registry_config: RegistryConfig,
+}
+
+/// Crate name and version requirement with the string representation `[crate-name]@[version requirement]`
+#[derive(Debug, Clone)]
+pub struct CrateSpec {
+    pub name: String,
+    pub req: VersionReq,
+}
+
+impl FromStr for CrateSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split: Vec<&str> = s.splitn(2, '@').collect();
+
+        let name = split[0].trim().to_string();
+        if name.is_empty() {
+            anyhow::bail!("Invalid crate format. Expected `[crate-name]@[version requirement]`");
+        }
+
+        // A bare version number means that exact version, the same as `cargo install --version`
+        let req = match split.get(1) {
+            Some(req) => match Version::parse(req.trim()) {
+                Ok(version) => VersionReq::exact(&version),
+                Err(_) => VersionReq::parse(req.trim())?,
+            },
+            None => VersionReq::any(),
+        };
+
+        Ok(Self { name, req })
+    }
+}
+
+impl CargoSideloadCommonArgs {
+    /// Path to the `Cargo.toml` file from either `--manifest-path` or `--path`
+    pub fn manifest_path(&self) -> PathBuf {
+        match &self.manifest_path {
+            Some(manifest_path) => manifest_path.clone(),
+            None => self.path.join("Cargo.toml"),
+        }
+    }
+}
+
+impl CargoSideloadResolveArgs {
+    /// Returns true if any of the arguments limit which packages are needed for the build
+    pub fn is_narrowed(&self) -> bool {
+        !self.features.is_empty()
+            || self.no_default_features
+            || self.target.is_some()
+            || self.no_dev_deps
+    }
+
+    /// Requested features with any comma separated lists split apart
+    pub fn features(&self) -> Vec<String> {
+        self.features
+            .iter()
+            .flat_map(|features| features.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|feature| !feature.is_empty())
+            .map(String::from)
+            .collect()
+    }
 }
 
 impl CargoSideloadArgs {
@@ -88,18 +539,104 @@ impl CargoSideloadArgs {
 
         let mut result = Self::parse_from(args);
 
-        // Add headers from the user's config file to the arg headers
-        if let CargoSideloadArgs::Fetch(ref mut fetch_args) = &mut result {
-            // Don't add default headers if headers are provided manually
-            if fetch_args.headers.is_empty() {
-                if let Some(registry) = config.registries.get(&fetch_args.common.registry) {
-                    for header in &registry.default_headers {
-                        fetch_args.headers.push(header.clone());
-                    }
-                }
+        // Add headers from the user's config file to the arg headers, along with the registry's settings for commands that download
+        match &mut result {
+            CargoSideloadArgs::Fetch(fetch_args) => {
+                add_default_headers(config, &fetch_args.common.registry, &mut fetch_args.headers);
+                fetch_args.registry_config = registry_config(config, &fetch_args.common.registry);
+            }
+            CargoSideloadArgs::Outdated(od_args) => {
+                add_default_headers(config, &od_args.common.registry, &mut od_args.headers);
+                od_args.registry_config = registry_config(config, &od_args.common.registry);
+            }
+            CargoSideloadArgs::Diff(diff_args) => {
+                add_default_headers(config, &diff_args.registry, &mut diff_args.headers);
+                diff_args.registry_config = registry_config(config, &diff_args.registry);
             }
+            CargoSideloadArgs::Download(download_args) => {
+                add_default_headers(config, &download_args.registry, &mut download_args.headers);
+                download_args.registry_config = registry_config(config, &download_args.registry);
+            }
+            CargoSideloadArgs::Verify(verify_args) => {
+                add_default_headers(config, &verify_args.registry, &mut verify_args.headers);
+                verify_args.registry_config = registry_config(config, &verify_args.registry);
+            }
+            CargoSideloadArgs::Publish(publish_args) => add_default_headers(
+                config,
+                &publish_args.common.registry,
+                &mut publish_args.headers,
+            ),
+            CargoSideloadArgs::Yank(yank_args) => {
+                add_default_headers(config, &yank_args.registry, &mut yank_args.headers)
+            }
+            CargoSideloadArgs::Owner(owner_args) => {
+                add_default_headers(config, &owner_args.registry, &mut owner_args.headers)
+            }
+            _ => (),
         }
 
         result
     }
 }
+
+fn add_default_headers(config: &Config, registry_name: &str, headers: &mut Vec<Header>) {
+    // Don't add default headers if headers are provided manually
+    if headers.is_empty() {
+        if let Some(registry) = config.registries.get(registry_name) {
+            for header in &registry.default_headers {
+                headers.push(header.clone());
+            }
+        }
+    }
+}
+
+fn registry_config(config: &Config, registry_name: &str) -> RegistryConfig {
+    config
+        .registries
+        .get(registry_name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crate_spec() {
+        let spec = CrateSpec::from_str("my_lib@1.2.3").unwrap();
+        assert_eq!(spec.name, "my_lib");
+        assert!(spec.req.matches(&Version::parse("1.2.3").unwrap()));
+        assert!(!spec.req.matches(&Version::parse("1.2.4").unwrap()));
+
+        let spec = CrateSpec::from_str("other_lib@^0.4").unwrap();
+        assert_eq!(spec.name, "other_lib");
+        assert!(spec.req.matches(&Version::parse("0.4.7").unwrap()));
+        assert!(!spec.req.matches(&Version::parse("0.5.0").unwrap()));
+
+        let spec = CrateSpec::from_str("my_lib").unwrap();
+        assert_eq!(spec.name, "my_lib");
+        assert!(spec.req.matches(&Version::parse("99.0.0").unwrap()));
+
+        assert!(CrateSpec::from_str("@1.2.3").is_err());
+        assert!(CrateSpec::from_str("my_lib@not a version").is_err());
+    }
+
+    #[test]
+    fn test_features() {
+        let args = CargoSideloadResolveArgs {
+            features: vec![
+                "first,second".to_string(),
+                "my_lib/third fourth".to_string(),
+            ],
+            no_default_features: false,
+            target: None,
+            no_dev_deps: false,
+        };
+
+        assert_eq!(
+            args.features(),
+            vec!["first", "second", "my_lib/third", "fourth"]
+        );
+    }
+}
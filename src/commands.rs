@@ -1,7 +1,27 @@
+pub mod cache;
+pub mod diff;
+pub mod download;
 pub mod fetch;
+pub mod info;
 pub mod list;
 pub mod outdated;
+pub mod owner;
+pub mod publish;
+pub mod registry;
+pub mod search;
+pub mod verify;
+pub mod yank;
 
+pub use cache::cache;
+pub use diff::diff;
+pub use download::download;
 pub use fetch::fetch;
+pub use info::info;
 pub use list::list;
 pub use outdated::outdated;
+pub use owner::owner;
+pub use publish::publish;
+pub use registry::registry;
+pub use search::search;
+pub use verify::verify;
+pub use yank::yank;
@@ -0,0 +1,183 @@
+use std::{collections::HashSet, time::Duration};
+
+use cargo::{core::source::Source, util::config::Config as CargoConfig};
+
+use crate::{
+    args::{
+        CargoSideloadCacheArgs, CargoSideloadCacheCommand, CargoSideloadCachePruneArgs,
+        CargoSideloadCacheRemoveArgs,
+    },
+    utils::{self, CachedCrate},
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub fn cache(args: CargoSideloadCacheArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+
+    match args.command {
+        CargoSideloadCacheCommand::List(list_args) => list(&cargo_config, &list_args.registry),
+        CargoSideloadCacheCommand::Prune(prune_args) => prune(&cargo_config, &prune_args),
+        CargoSideloadCacheCommand::Remove(remove_args) => remove(&cargo_config, &remove_args),
+    }
+}
+
+fn list(config: &CargoConfig, registry_name: &str) -> anyhow::Result<()> {
+    let source_id = utils::create_registry(config, registry_name)?.source_id();
+    let cached_crates = utils::cached_crates(config, source_id)?;
+
+    if cached_crates.is_empty() {
+        println!("No crates cached for {}", registry_name);
+        return Ok(());
+    }
+
+    let mut total_size = 0;
+    let mut rows = Vec::new();
+    for cached_crate in &cached_crates {
+        let (crate_size, source_size) = cached_crate.size()?;
+        total_size += crate_size + source_size.unwrap_or(0);
+
+        rows.push(vec![
+            cached_crate.name.clone(),
+            cached_crate.version.to_string(),
+            format_size(crate_size),
+            source_size
+                .map(format_size)
+                .unwrap_or_else(|| "---".to_string()),
+            format!("{}d", cached_crate.age().as_secs() / SECONDS_PER_DAY),
+        ]);
+    }
+
+    utils::print_table(&["Name", "Version", "Crate", "Source", "Age"], &rows);
+    println!(
+        "{} cached versions using {}",
+        cached_crates.len(),
+        format_size(total_size)
+    );
+
+    Ok(())
+}
+
+fn prune(config: &CargoConfig, args: &CargoSideloadCachePruneArgs) -> anyhow::Result<()> {
+    if args.lockfiles.is_empty() && args.older_than.is_none() {
+        anyhow::bail!("Use --lockfile or --older-than to choose which versions to prune");
+    }
+
+    let source_id = utils::create_registry(config, &args.registry)?.source_id();
+
+    // Versions used by any of the lockfiles are kept
+    let mut referenced = HashSet::new();
+    for path in &args.lockfiles {
+        let path = if path.is_dir() {
+            path.join("Cargo.lock")
+        } else {
+            path.clone()
+        };
+
+        for package in utils::lockfile_packages(&path, source_id)? {
+            referenced.insert((package.name, package.version));
+        }
+    }
+
+    let max_age = args
+        .older_than
+        .map(|days| Duration::from_secs(days * SECONDS_PER_DAY));
+
+    let _package_cache_lock = config.acquire_package_cache_lock()?;
+
+    let mut freed = 0;
+    let mut removed = 0;
+    for cached_crate in utils::cached_crates(config, source_id)? {
+        let is_referenced =
+            referenced.contains(&(cached_crate.name.clone(), cached_crate.version.clone()));
+        let is_old = max_age.map_or(true, |max_age| cached_crate.age() > max_age);
+        if is_referenced || !is_old {
+            continue;
+        }
+
+        if args.dry_run {
+            println!(
+                "{}-{} would be removed",
+                cached_crate.name, cached_crate.version
+            );
+        } else {
+            freed += cached_crate.remove()?;
+            println!("Removed {}-{}", cached_crate.name, cached_crate.version);
+        }
+        removed += 1;
+    }
+
+    if removed == 0 {
+        println!("No versions to prune");
+    } else if !args.dry_run {
+        println!(
+            "Removed {} versions, freeing {}",
+            removed,
+            format_size(freed)
+        );
+    }
+
+    Ok(())
+}
+
+fn remove(config: &CargoConfig, args: &CargoSideloadCacheRemoveArgs) -> anyhow::Result<()> {
+    let source_id = utils::create_registry(config, &args.registry)?.source_id();
+    let spec = &args.crate_spec;
+
+    let _package_cache_lock = config.acquire_package_cache_lock()?;
+
+    let matching: Vec<CachedCrate> = utils::cached_crates(config, source_id)?
+        .into_iter()
+        .filter(|cached_crate| {
+            cached_crate.name == spec.name && spec.req.matches(&cached_crate.version)
+        })
+        .collect();
+
+    if matching.is_empty() {
+        anyhow::bail!("No cached versions of {} match `{}`", spec.name, spec.req);
+    }
+
+    for cached_crate in matching {
+        let freed = cached_crate.remove()?;
+        println!(
+            "Removed {}-{}, freeing {}",
+            cached_crate.name,
+            cached_crate.version,
+            format_size(freed)
+        );
+    }
+
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next_unit in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+
+    format!("{:.1} {}", size, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use cargo::{core::Verbosity, util::config::Config as CargoConfig};
+use semver::{Version, VersionReq};
+use similar::TextDiff;
+
+use crate::{args::CargoSideloadDiffArgs, downloader::Downloader, manifest, utils};
+
+pub fn diff(args: CargoSideloadDiffArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+    if args.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+
+    // Yanked versions can still be compared
+    for version in &[&args.from, &args.to] {
+        let req = VersionReq::exact(version);
+        let summaries =
+            utils::package_summaries_with_yanked(&cargo_config, &mut registry, &args.name, &req)?;
+        if utils::matching_version(&summaries, &req).is_none() {
+            anyhow::bail!("Package {} {} not found", args.name, version);
+        }
+    }
+
+    let mut downloader = Downloader::new(
+        &cargo_config,
+        &args.registry,
+        args.headers.clone(),
+        &args.registry_config,
+        false,
+    )?;
+
+    let old_dir = tempfile::tempdir()?;
+    let new_dir = tempfile::tempdir()?;
+    let old_root = unpack(&mut downloader, &args.name, &args.from, old_dir.path())?;
+    let new_root = unpack(&mut downloader, &args.name, &args.to, new_dir.path())?;
+
+    // Summarize the changes to dependencies and features before showing the full diff
+    let old_manifest = fs::read_to_string(old_root.join("Cargo.toml"))?;
+    let new_manifest = fs::read_to_string(new_root.join("Cargo.toml"))?;
+    let changes = manifest::manifest_changes(&old_manifest, &new_manifest)?;
+
+    println!();
+    println!(
+        "Manifest changes from {} {} to {}:",
+        args.name, args.from, args.to
+    );
+    if changes.is_empty() {
+        println!("  none");
+    }
+    for change in changes {
+        println!("  {}", change);
+    }
+    println!();
+
+    let mut paths = BTreeSet::new();
+    collect_files(&old_root, Path::new(""), &mut paths)?;
+    collect_files(&new_root, Path::new(""), &mut paths)?;
+
+    for path in paths {
+        print_file_diff(&old_root, &new_root, &path)?;
+    }
+
+    Ok(())
+}
+
+/// Downloads the `.crate` file for the version and unpacks it into the directory, leaving the package cache untouched.
+/// Returns the path to the unpacked package.
+fn unpack(
+    downloader: &mut Downloader,
+    name: &str,
+    version: &Version,
+    directory: &Path,
+) -> anyhow::Result<PathBuf> {
+    let crate_file = directory.join(format!("{}-{}.crate", name, version));
+    fs::write(&crate_file, downloader.fetch(name, &version.to_string())?)?;
+    utils::unpack_crate(&crate_file, directory)?;
+    Ok(directory.join(format!("{}-{}", name, version)))
+}
+
+/// Adds the path of every file in the directory to `paths`, relative to the root directory
+fn collect_files(
+    root: &Path,
+    relative: &Path,
+    paths: &mut BTreeSet<PathBuf>,
+) -> anyhow::Result<()> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, paths)?;
+        } else {
+            paths.insert(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a unified diff of the file between the two versions.
+/// A file that only exists in one of the versions is compared to an empty file.
+fn print_file_diff(old_root: &Path, new_root: &Path, path: &Path) -> anyhow::Result<()> {
+    let old = read_file(&old_root.join(path))?;
+    let new = read_file(&new_root.join(path))?;
+    if old == new {
+        return Ok(());
+    }
+
+    // Paths in the diff always use forward slashes, like git
+    let display_path: Vec<String> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect();
+    let display_path = display_path.join("/");
+    let old_name = match old {
+        Some(_) => format!("a/{}", display_path),
+        None => "/dev/null".to_string(),
+    };
+    let new_name = match new {
+        Some(_) => format!("b/{}", display_path),
+        None => "/dev/null".to_string(),
+    };
+
+    match (as_text(&old), as_text(&new)) {
+        (Some(old_text), Some(new_text)) => print!(
+            "{}",
+            TextDiff::from_lines(old_text, new_text)
+                .unified_diff()
+                .header(&old_name, &new_name)
+        ),
+        _ => println!("Binary files {} and {} differ", old_name, new_name),
+    }
+
+    Ok(())
+}
+
+/// Returns the contents of a file as text, or `None` if it's a binary file.
+/// Like git, files that contain a null byte are treated as binary.
+fn as_text(contents: &Option<Vec<u8>>) -> Option<&str> {
+    let contents = contents.as_deref().unwrap_or_default();
+    if contents.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(contents).ok()
+}
+
+/// Reads a file, returning `None` if it doesn't exist
+fn read_file(path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
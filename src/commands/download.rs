@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use cargo::{core::Verbosity, util::config::Config as CargoConfig};
+
+use crate::{args::CargoSideloadDownloadArgs, downloader::Downloader, utils};
+
+pub fn download(args: CargoSideloadDownloadArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+    if args.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    let name = &args.crate_spec.name;
+    let req = &args.crate_spec.req;
+
+    let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+
+    // Yanked versions can only be downloaded if no other version matches
+    let mut summaries = utils::package_summaries(&cargo_config, &mut registry, name)?;
+    if utils::matching_version(&summaries, req).is_none() {
+        summaries = utils::package_summaries_with_yanked(&cargo_config, &mut registry, name, req)?;
+    }
+
+    let summary = match utils::matching_version(&summaries, req) {
+        Some(summary) => summary,
+        None => anyhow::bail!("Package {} matching `{}` not found", name, req),
+    };
+    let version = summary.version().to_string();
+
+    let directory = args
+        .extract
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}-{}", name, version)));
+    if directory
+        .read_dir()
+        .map_or(false, |mut dir| dir.next().is_some())
+    {
+        anyhow::bail!("{} already exists and is not empty", directory.display());
+    }
+
+    let mut downloader = Downloader::new(
+        &cargo_config,
+        &args.registry,
+        args.headers.clone(),
+        &args.registry_config,
+        args.force,
+    )?;
+    // The downloader checks the file against the index's checksum before it's cached
+    let crate_file = downloader.download_crate(name, &version)?;
+    if summary.checksum().is_none() {
+        println!(
+            "The registry index has no checksum for {}-{}",
+            name, version
+        );
+    }
+
+    std::fs::create_dir_all(&directory)?;
+    utils::extract_crate(&crate_file, &directory)?;
+    println!("{}-{} extracted to {}", name, version, directory.display());
+
+    Ok(())
+}
@@ -1,162 +1,157 @@
-use std::fs::canonicalize;
+use std::fs;
 
 use cargo::{
-    core::{
-        package_id::PackageId,
-        source::{MaybePackage, Source},
-        Verbosity, Workspace,
-    },
-    sources::registry::RegistrySource,
-    util::{config::Config as CargoConfig, Filesystem},
+    core::{package_id::PackageId, resolver::ResolveOpts, Verbosity},
+    util::config::Config as CargoConfig,
 };
-use log::debug;
 
-use crate::{args::CargoSideloadFetchArgs, utils};
+use crate::{
+    args::CargoSideloadFetchArgs,
+    downloader::{ArtifactChanged, Downloader},
+    utils,
+};
 
 pub fn fetch(args: CargoSideloadFetchArgs) -> anyhow::Result<()> {
-    let cargo_config = CargoConfig::default()?;
-    if args.common.quiet {
-        cargo_config.shell().set_verbosity(Verbosity::Quiet);
-    }
-
-    let manifest_path = canonicalize(args.common.path.join("Cargo.toml"))?;
-    let workspace = Workspace::new(&manifest_path, &cargo_config)?;
-
-    let mut downloader = Downloader::new(&cargo_config, &args)?;
-
-    for package_id in utils::workspace_packages(&cargo_config, &args.common, &workspace)? {
-        downloader.download(&package_id.name(), &package_id.version().to_string())?;
-    }
-
-    Ok(())
+    fetch_with_config(CargoConfig::default()?, args)
 }
 
-/// Downloads packages from a particular registry
-struct Downloader<'cfg> {
-    config: &'cfg CargoConfig,
-    registry: RegistrySource<'cfg>,
-    client: reqwest::blocking::Client,
+fn fetch_with_config(
+    mut cargo_config: CargoConfig,
     args: CargoSideloadFetchArgs,
-}
-
-impl<'cfg> Downloader<'cfg> {
-    pub fn new(config: &'cfg CargoConfig, args: &CargoSideloadFetchArgs) -> anyhow::Result<Self> {
-        let registry = utils::create_registry(&config, &args.common.registry)?;
-        let client = reqwest::blocking::Client::new();
-
-        Ok(Self {
-            config,
-            registry,
-            client,
-            args: args.clone(),
-        })
+) -> anyhow::Result<()> {
+    utils::configure(&mut cargo_config, &args.common)?;
+    if args.common.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
     }
 
-    /// Download the specified version of a package.
-    pub fn download(&mut self, name: &str, version: &str) -> anyhow::Result<()> {
-        let source_id = self.registry.source_id();
-        let package_id = PackageId::new(name, version, source_id)?;
-
-        if self.args.force {
-            self.delete_existing(package_id)?;
-        }
-
-        match self.package_status(package_id)? {
-            MaybePackage::Ready(_) => println!(
-                "{}-{} is already cached.",
-                package_id.name(),
-                package_id.version()
-            ),
-            MaybePackage::Download { url, .. } => self.download_package(package_id, &url)?,
-        }
-
-        Ok(())
+    let mut downloader = Downloader::new(
+        &cargo_config,
+        &args.common.registry,
+        args.headers.clone(),
+        &args.registry_config,
+        args.force,
+    )?;
+    if args.require_signatures {
+        downloader.require_signatures()?;
     }
 
-    /// Checks if the .crate file is already in the cache. If it is, it will also be unpacked by Cargo.
-    fn package_status(&mut self, package_id: PackageId) -> anyhow::Result<MaybePackage> {
-        let _package_cache_lock = self.config.acquire_package_cache_lock()?;
-        // This method won't actually start a download.
-        // If the .crate file is already in the cache it'll unpack it, otherwise it will return the download url
-        let result = self.registry.download(package_id);
-
-        if result.is_err() {
-            println!(
-                "Failed to unpack crate file for {}. Double check your download url and headers.",
-                package_id.name()
-            );
-            self.delete_existing(package_id)?;
+    if args.dry_run {
+        for header in &args.headers {
+            println!("Header {}", header.redacted());
         }
-
-        result
     }
 
-    /// Perform the actual download
-    fn download_package(&mut self, package_id: PackageId, url: &str) -> anyhow::Result<()> {
-        debug!("Downloading: {}", url);
+    let mut original_lockfile = None;
+    let packages = if args.crates.is_empty() {
+        let workspace = utils::workspace(&cargo_config, &args.common)?;
 
-        let mut request_builder = self.client.get(url);
-
-        for header in &self.args.headers {
-            request_builder = request_builder.header(&header.name, &header.value);
+        // The checksums are read before resolving, because regenerating `Cargo.lock` replaces them with the ones in the index
+        let lockfile_path = workspace.root().join("Cargo.lock");
+        if lockfile_path.is_file() {
+            downloader.read_locked_checksums(&lockfile_path)?;
+            original_lockfile = Some((lockfile_path.clone(), fs::read(&lockfile_path)?));
         }
 
-        let request = request_builder.build()?;
-        debug!("{:#?}", request);
-
-        let response = self.client.execute(request)?;
-        debug!("{:#?}", response);
-
-        let body = response.error_for_status()?.bytes()?;
-        debug!("BODY");
-        debug!("{}", String::from_utf8_lossy(&body));
-
-        let file_name = format!("{}-{}.crate", package_id.name(), package_id.version());
+        // A dry run resolves the workspace in memory, so `Cargo.lock` is left as it is
+        let mut common = args.common.clone();
+        if args.dry_run {
+            common.no_write_lockfile = true;
+        }
 
+        utils::workspace_packages(&cargo_config, &common, &args.resolve, &workspace)?
+    } else if args.deps {
+        let manifest_dir = tempfile::tempdir()?;
+        let workspace = utils::crates_workspace(
+            &cargo_config,
+            &args.common.registry,
+            &args.crates,
+            &args.resolve.features(),
+            !args.resolve.no_default_features,
+            manifest_dir.path(),
+        )?;
+        let members: Vec<PackageId> = workspace
+            .members()
+            .map(|member| member.package_id())
+            .collect();
+        // Features are set in the generated manifest, so they're not requested again here
+        let resolve_opts = ResolveOpts::new(false, &[], false, true);
+
+        utils::resolved_packages(
+            &cargo_config,
+            &args.common.registry,
+            &workspace,
+            &members,
+            None,
+            &resolve_opts,
+            args.resolve.target.as_deref(),
+        )?
+    } else {
+        let mut registry = utils::create_registry(&cargo_config, &args.common.registry)?;
+        utils::update_index(&cargo_config, &mut registry)?;
+
+        utils::registry_packages(&cargo_config, &mut registry, &args.crates)?
+    };
+
+    for package_id in packages {
+        if args.dry_run {
+            downloader.plan(&package_id.name(), &package_id.version().to_string())?;
+        } else if let Err(e) =
+            downloader.download(&package_id.name(), &package_id.version().to_string())
         {
-            let file_lock =
-                self.target_dir()
-                    .open_rw(file_name, &self.config, "Waiting for file lock...")?;
-
-            let file_path = file_lock.path();
-
-            std::fs::write(file_path, body)?;
-            println!(
-                "{}-{} added to package cache",
-                package_id.name(),
-                package_id.version()
-            );
-            debug!("Download path: {:?}", file_path);
+            // Put back the checksum that changed so the next fetch raises the same alarm
+            if let (Some(_), Some((path, contents))) =
+                (e.downcast_ref::<ArtifactChanged>(), &original_lockfile)
+            {
+                if fs::read(path)? != *contents {
+                    fs::write(path, contents)?;
+                    println!("Restored {}", path.display());
+                }
+            }
+            return Err(e);
         }
-
-        // The code to unpack the crate is private, but we can trigger it by calling `Source::download` again.
-        // This will see that the cached file is already present and attempt to unpack it.
-        self.package_status(package_id)?;
-
-        Ok(())
-    }
-
-    /// Package cache path for the specified registry
-    fn target_dir(&self) -> Filesystem {
-        let registry_directory = utils::registry_directory(self.registry.source_id());
-        self.config.registry_cache_path().join(&registry_directory)
     }
 
-    /// Deletes an existing cached package file
-    fn delete_existing(&self, package_id: PackageId) -> anyhow::Result<()> {
-        let name = package_id.name();
-        let version = package_id.version().to_string();
-
-        let file_name = format!("{}-{}.crate", name, version);
-        let file_lock =
-            self.target_dir()
-                .open_rw(file_name, &self.config, "Waiting for file lock...")?;
-
-        let file_path = file_lock.path();
-
-        std::fs::remove_file(file_path)?;
-        debug!("Removed: {:?}", file_path);
+    Ok(())
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo::core::Shell;
+    use clap::Clap;
+
+    #[test]
+    fn test_dry_run_keeps_lockfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("my_app");
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"my_app\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(project.join("src").join("lib.rs"), "").unwrap();
+        // Cargo would add its header if it wrote the lockfile
+        let lockfile = "[[package]]\nname = \"my_app\"\nversion = \"0.1.0\"\n";
+        fs::write(project.join("Cargo.lock"), lockfile).unwrap();
+
+        let home = dir.path().join("home");
+        fs::create_dir_all(&home).unwrap();
+        let config = CargoConfig::new(Shell::new(), dir.path().to_path_buf(), home);
+        let manifest_path = project.join("Cargo.toml");
+        let args = CargoSideloadFetchArgs::parse_from(&[
+            "fetch",
+            "--registry",
+            "crates-io",
+            "--manifest-path",
+            manifest_path.to_str().unwrap(),
+            "--dry-run",
+        ]);
+
+        fetch_with_config(config, args).unwrap();
+        assert_eq!(
+            fs::read_to_string(project.join("Cargo.lock")).unwrap(),
+            lockfile
+        );
     }
 }
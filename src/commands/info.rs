@@ -0,0 +1,141 @@
+use cargo::{
+    core::{dependency::DepKind, source::Source, Dependency, Summary, Verbosity},
+    util::config::Config as CargoConfig,
+};
+
+use crate::{args::CargoSideloadInfoArgs, utils};
+
+pub fn info(args: CargoSideloadInfoArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+    if args.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+
+    let name = &args.crate_spec.name;
+    let req = &args.crate_spec.req;
+
+    let mut summaries = utils::package_summaries(&cargo_config, &mut registry, name)?;
+
+    // Yanked versions are only shown if no other version matches
+    if utils::matching_version(&summaries, req).is_none() {
+        summaries = utils::package_summaries_with_yanked(&cargo_config, &mut registry, name, req)?;
+    }
+
+    let summary = match utils::matching_version(&summaries, req) {
+        Some(summary) => summary,
+        None => anyhow::bail!("Package {} matching `{}` not found", name, req),
+    };
+
+    let is_yanked = {
+        let _package_cache_lock = cargo_config.acquire_package_cache_lock()?;
+        registry.is_yanked(summary.package_id())?
+    };
+    let rust_versions = utils::rust_versions(&cargo_config, registry.source_id(), &summaries);
+
+    let none = || "---".to_string();
+
+    println!("{} {}", summary.name(), summary.version());
+    println!("Registry:      {}", args.registry);
+    println!(
+        "Checksum:      {}",
+        summary.checksum().map(str::to_string).unwrap_or_else(none)
+    );
+    println!(
+        "Links:         {}",
+        summary
+            .links()
+            .map(|links| links.to_string())
+            .unwrap_or_else(none)
+    );
+    println!(
+        "Rust version:  {}",
+        rust_versions
+            .get(summary.version())
+            .map(|rust_version| rust_version.to_string())
+            .unwrap_or_else(none)
+    );
+    println!("Yanked:        {}", if is_yanked { "yes" } else { "no" });
+
+    print_features(summary);
+
+    for (kind, title) in &[
+        (DepKind::Normal, "Dependencies"),
+        (DepKind::Build, "Build dependencies"),
+        (DepKind::Development, "Dev dependencies"),
+    ] {
+        let dependencies: Vec<&Dependency> = summary
+            .dependencies()
+            .iter()
+            .filter(|dep| dep.kind() == *kind)
+            .collect();
+        print_dependencies(title, &dependencies, summary, &args.registry);
+    }
+
+    Ok(())
+}
+
+/// Prints each feature along with the features and dependencies that it activates
+fn print_features(summary: &Summary) {
+    println!();
+    if summary.features().is_empty() {
+        println!("Features: none");
+        return;
+    }
+
+    println!("Features:");
+    for (feature, activations) in summary.features() {
+        let activations: Vec<String> = activations
+            .iter()
+            .map(|activation| format!("\"{}\"", activation))
+            .collect();
+        println!("  {} = [{}]", feature, activations.join(", "));
+    }
+}
+
+fn print_dependencies(
+    title: &str,
+    dependencies: &[&Dependency],
+    summary: &Summary,
+    registry_name: &str,
+) {
+    if dependencies.is_empty() {
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = dependencies
+        .iter()
+        .map(|dep| {
+            // Renamed dependencies show both the name used in the code and the package name
+            let name = if dep.name_in_toml() == dep.package_name() {
+                dep.package_name().to_string()
+            } else {
+                format!("{} ({})", dep.name_in_toml(), dep.package_name())
+            };
+
+            let source = if dep.source_id() == summary.source_id() {
+                registry_name.to_string()
+            } else if dep.source_id().is_default_registry() {
+                "crates.io".to_string()
+            } else {
+                dep.source_id().url().to_string()
+            };
+
+            vec![
+                name,
+                dep.version_req().to_string(),
+                if dep.is_optional() { "yes" } else { "no" }.to_string(),
+                dep.platform()
+                    .map(|platform| platform.to_string())
+                    .unwrap_or_else(|| "---".to_string()),
+                source,
+            ]
+        })
+        .collect();
+
+    println!();
+    println!("{}:", title);
+    utils::print_table(&["Name", "Req", "Optional", "Target", "Source"], &rows);
+}
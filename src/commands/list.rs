@@ -1,7 +1,9 @@
 use cargo::{
-    core::{Summary, Verbosity},
-    util::config::Config as CargoConfig,
+    core::{Source, Summary, Verbosity, Workspace},
+    util::{config::Config as CargoConfig, important_paths::find_root_manifest_for_wd},
 };
+use log::debug;
+use semver::{Version, VersionReq};
 
 use crate::{args::CargoSideloadListArgs, utils};
 
@@ -14,46 +16,182 @@ pub fn list(args: CargoSideloadListArgs) -> anyhow::Result<()> {
     let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
 
     utils::update_index(&cargo_config, &mut registry)?;
-    let summaries = utils::package_summaries(&cargo_config, &mut registry, &args.name)?;
+    let (mut summaries, yanked) = if args.include_yanked {
+        let yanked = utils::yanked_versions(&cargo_config, registry.source_id(), &args.name)?;
+        let summaries = utils::package_summaries_with_yanked(
+            &cargo_config,
+            &mut registry,
+            &args.name,
+            &VersionReq::any(),
+        )?;
+        (summaries, Some(yanked))
+    } else {
+        let summaries = utils::package_summaries(&cargo_config, &mut registry, &args.name)?;
+        (summaries, None)
+    };
+
+    summaries.retain(|summary| {
+        args.req
+            .as_ref()
+            .map_or(true, |req| req.matches(summary.version()))
+            && args
+                .since
+                .as_ref()
+                .map_or(true, |since| summary.version() >= since)
+    });
+
+    // Summaries are sorted from oldest to newest, so the newest ones are at the end
+    if let Some(limit) = args.limit {
+        summaries.drain(..summaries.len().saturating_sub(limit));
+    }
 
     if args.latest {
-        print_latest(&summaries, args.version_only);
+        let rust_version = match args.rust_version {
+            Some(rust_version) => Some(rust_version),
+            None => current_rust_version(&cargo_config),
+        };
+
+        // The `rust-version` of each version is only needed to skip versions or to print it
+        let rust_versions = if rust_version.is_some() || !args.version_only {
+            utils::rust_versions(&cargo_config, registry.source_id(), &summaries)
+        } else {
+            utils::RustVersions::new()
+        };
+        print_latest(
+            &summaries,
+            &rust_versions,
+            rust_version.as_ref(),
+            args.version_only,
+            args.include_prerelease,
+        );
     } else {
-        print_published(&summaries, args.version_only);
+        let rust_versions = if args.version_only {
+            utils::RustVersions::new()
+        } else {
+            utils::rust_versions(&cargo_config, registry.source_id(), &summaries)
+        };
+        print_published(
+            &summaries,
+            &rust_versions,
+            yanked.as_deref(),
+            args.version_only,
+        );
     }
 
     Ok(())
 }
 
-fn print_published(summaries: &[Summary], version_only: bool) {
+/// Returns the lowest `rust-version` of the package or workspace in the current directory, if there is one
+fn current_rust_version(config: &CargoConfig) -> Option<Version> {
+    let manifest_path = find_root_manifest_for_wd(config.cwd()).ok()?;
+
+    let rust_version = Workspace::new(&manifest_path, config).and_then(|workspace| {
+        let members = match workspace.current_opt() {
+            Some(package) => vec![package.package_id()],
+            None => workspace
+                .members()
+                .map(|member| member.package_id())
+                .collect(),
+        };
+        utils::workspace_rust_version(&workspace, &members)
+    });
+
+    match rust_version {
+        Ok(rust_version) => rust_version,
+        Err(e) => {
+            debug!(
+                "Unable to read the rust-version of the current package: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Prints every version. If the yanked versions are provided, each version is marked as yanked or live.
+fn print_published(
+    summaries: &[Summary],
+    rust_versions: &utils::RustVersions,
+    yanked: Option<&[Version]>,
+    version_only: bool,
+) {
     for summary in summaries {
+        let status = yanked.map(|yanked| {
+            if yanked.contains(summary.version()) {
+                "yanked"
+            } else {
+                "live"
+            }
+        });
+
         if version_only {
-            println!("{}", summary.version())
+            match status {
+                Some(status) => println!("{} {}", summary.version(), status),
+                None => println!("{}", summary.version()),
+            }
         } else {
-            print_summary(summary);
+            print_summary(summary, rust_versions.get(summary.version()), status);
         }
     }
 }
 
-fn print_latest(summaries: &[Summary], version_only: bool) {
-    let latest_version = utils::latest_version(summaries);
+fn print_latest(
+    summaries: &[Summary],
+    rust_versions: &utils::RustVersions,
+    rust_version: Option<&Version>,
+    version_only: bool,
+    include_prerelease: bool,
+) {
+    // Skip versions that need a newer Rust version
+    let (supported, unsupported): (Vec<Summary>, Vec<Summary>) =
+        summaries.iter().cloned().partition(|summary| {
+            rust_version.map_or(true, |rust_version| {
+                utils::supports_rust_version(rust_versions, summary.version(), rust_version)
+            })
+        });
+
+    let latest_version = utils::latest_version(&supported, include_prerelease);
+
+    if let (Some(rust_version), Some(skipped)) = (
+        rust_version,
+        utils::latest_version(&unsupported, include_prerelease),
+    ) {
+        if latest_version.map_or(true, |latest| skipped.version() > latest.version()) {
+            eprintln!(
+                "Skipped {} {}, which needs Rust {} (newer than {})",
+                skipped.name(),
+                skipped.version(),
+                rust_versions[skipped.version()],
+                rust_version
+            );
+        }
+    }
 
     match (latest_version, version_only) {
         (Some(latest), true) => println!("{}", latest.version()),
-        (Some(latest), false) => print_summary(latest),
+        (Some(latest), false) => print_summary(latest, rust_versions.get(latest.version()), None),
         _ => println!("Package not found"),
     }
 }
 
-fn print_summary(summary: &Summary) {
+fn print_summary(summary: &Summary, rust_version: Option<&Version>, status: Option<&str>) {
+    let rust_version = rust_version
+        .map(|rust_version| format!("\n    \"rust_version\": \"{}\",", rust_version))
+        .unwrap_or_default();
+    let status = status
+        .map(|status| format!("\n    \"status\": \"{}\",", status))
+        .unwrap_or_default();
+
     println!(
         r#"{{
     "name": "{}",
     "version": "{}",
-    "checksum": "{:?}",
+    "checksum": "{:?}",{}{}
 }}"#,
         summary.name(),
         summary.version(),
-        summary.checksum()
+        summary.checksum(),
+        rust_version,
+        status
     )
 }
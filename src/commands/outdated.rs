@@ -1,61 +1,455 @@
-use std::fs::canonicalize;
+use std::{fmt::Display, fs};
 
 use cargo::{
-    core::{source::Source, Verbosity, Workspace},
+    core::{source::Source, PackageId, Verbosity},
     util::config::Config as CargoConfig,
 };
+use semver::Version;
 
-use crate::{args::CargoSideloadOutdatedArgs, utils};
+use crate::{
+    args::{CargoSideloadFetchArgs, CargoSideloadOutdatedArgs},
+    manifest::{self, Update},
+    utils::{self, DependencyPaths},
+};
 
 pub fn outdated(args: CargoSideloadOutdatedArgs) -> anyhow::Result<()> {
-    let cargo_config = CargoConfig::default()?;
+    let mut cargo_config = CargoConfig::default()?;
+    utils::configure(&mut cargo_config, &args.common)?;
     if args.common.quiet {
         cargo_config.shell().set_verbosity(Verbosity::Quiet);
     }
 
-    let manifest_path = canonicalize(args.common.path.join("Cargo.toml"))?;
-    let workspace = Workspace::new(&manifest_path, &cargo_config)?;
+    let workspace = utils::workspace(&cargo_config, &args.common)?;
 
     let mut registry = utils::create_registry(&cargo_config, &args.common.registry)?;
-    let packages = utils::workspace_packages(&cargo_config, &args.common, &workspace)?;
+    let lock_file = utils::workspace_resolve(&cargo_config, &args.common, &workspace)?;
+    let packages = utils::registry_packages_in_resolve(
+        &cargo_config,
+        &args.common,
+        &args.resolve,
+        &workspace,
+        &lock_file,
+    )?;
 
     utils::update_index(&cargo_config, &mut registry)?;
 
+    // The shortest path from each selected member to each of its dependencies
+    let max_depth = if args.direct_only {
+        Some(1)
+    } else {
+        args.depth
+    };
+    let members = utils::selected_members(&args.common, &workspace)?;
+    let member_paths: Vec<(PackageId, DependencyPaths)> = members
+        .iter()
+        .copied()
+        .map(|member| {
+            let mut paths = utils::dependency_paths(&lock_file, member);
+            paths.retain(|_, path| max_depth.map_or(true, |depth| path.len() - 1 <= depth));
+            (member, paths)
+        })
+        .collect();
+
+    // Versions that need a newer Rust version than this aren't suggested
+    let rust_version = match &args.rust_version {
+        Some(rust_version) => Some(rust_version.clone()),
+        None => utils::workspace_rust_version(&workspace, &members)?,
+    };
+
     let mut has_outdated_packages = false;
+    let mut rows = Vec::new();
+    let mut yanked_rows = Vec::new();
+    let mut has_yanked_without_replacement = false;
+    let mut updates = Vec::new();
+    let mut rust_version_rows = Vec::new();
 
-    let _package_cache_lock = cargo_config.acquire_package_cache_lock()?;
+    let package_cache_lock = cargo_config.acquire_package_cache_lock()?;
 
     for package_id in packages {
-        // Check if the package is yanked
+        if !member_paths
+            .iter()
+            .any(|(_, paths)| paths.contains_key(&package_id))
+        {
+            continue;
+        }
+
+        let mut summaries =
+            utils::package_summaries(&cargo_config, &mut registry, &package_id.name())?;
+
+        let mut unsupported_summaries = Vec::new();
+        let mut rust_versions = utils::RustVersions::new();
+        if let Some(rust_version) = &rust_version {
+            rust_versions = utils::rust_versions(&cargo_config, registry.source_id(), &summaries);
+            let (supported, unsupported) = summaries.into_iter().partition(|summary| {
+                utils::supports_rust_version(&rust_versions, summary.version(), rust_version)
+            });
+            summaries = supported;
+            unsupported_summaries = unsupported;
+        }
+
+        let latest_version_summary =
+            utils::latest_update(&summaries, package_id.version(), args.include_prerelease);
+
+        // Flag newer versions that were skipped because they need a newer Rust version
+        let newest_suggestion = latest_version_summary
+            .map(|summary| summary.version())
+            .filter(|version| *version > package_id.version())
+            .unwrap_or_else(|| package_id.version());
+        if let Some(skipped) = utils::latest_update(
+            &unsupported_summaries,
+            package_id.version(),
+            args.include_prerelease,
+        )
+        .filter(|summary| summary.version() > newest_suggestion)
+        {
+            rust_version_rows.push(vec![
+                package_id.name().to_string(),
+                skipped.version().to_string(),
+                rust_versions[skipped.version()].to_string(),
+            ]);
+        }
+
+        // The requirements on this package, which the suggested versions need to satisfy
+        let reqs = utils::version_requirements(
+            &cargo_config,
+            &mut registry,
+            &workspace,
+            &lock_file,
+            package_id,
+        )?;
+
+        // Yanked packages are always reported, along with the version that should replace them
         if registry.is_yanked(package_id)? {
-            println!("{} {} -> yanked", package_id.name(), package_id.version());
             has_outdated_packages = true;
+
+            let replacement = utils::nearest_version(&summaries, &reqs, package_id.version())
+                .map(|summary| summary.version())
+                .filter(|_| !reqs.is_empty());
+            let latest = latest_version_summary.map(|summary| summary.version());
+            has_yanked_without_replacement |= replacement.is_none();
+
+            let update_version = if args.latest { latest } else { replacement };
+            if let Some(version) = update_version.filter(|version| *version > package_id.version())
+            {
+                updates.push(Update {
+                    name: package_id.name().to_string(),
+                    current: package_id.version().clone(),
+                    version: version.clone(),
+                });
+            }
+
+            yanked_rows.push((
+                package_id,
+                vec![
+                    package_id.name().to_string(),
+                    package_id.version().to_string(),
+                    replacement
+                        .map(Version::to_string)
+                        .unwrap_or_else(|| "none".to_string()),
+                    latest
+                        .map(Version::to_string)
+                        .unwrap_or_else(|| "---".to_string()),
+                ],
+            ));
             continue;
         }
 
-        // Check if the version number is behind the latest
-        let summaries = utils::package_summaries(&cargo_config, &mut registry, &package_id.name())?;
-        let latest_version_summary = utils::latest_version(&summaries);
+        // The newest version that still satisfies every requirement on this package
+        let compatible_version = utils::compatible_version(&summaries, &reqs)
+            .map(|summary| summary.version())
+            .filter(|version| !reqs.is_empty() && *version > package_id.version());
 
         match latest_version_summary {
             Some(latest) => {
-                if package_id.version() < latest.version() {
-                    has_outdated_packages = true;
-                    println!(
-                        "{} {} -> {}",
-                        package_id.name(),
-                        package_id.version(),
-                        latest.version()
-                    );
+                if package_id.version() >= latest.version() {
+                    continue;
+                }
+                if args.compatible_only && compatible_version.is_none() {
+                    continue;
                 }
+
+                has_outdated_packages = true;
+
+                let update_version = if args.latest {
+                    Some(latest.version())
+                } else {
+                    compatible_version
+                };
+                if let Some(version) = update_version {
+                    updates.push(Update {
+                        name: package_id.name().to_string(),
+                        current: package_id.version().clone(),
+                        version: version.clone(),
+                    });
+                }
+
+                rows.push((
+                    package_id,
+                    vec![
+                        package_id.name().to_string(),
+                        package_id.version().to_string(),
+                        compatible_version
+                            .map(Version::to_string)
+                            .unwrap_or_else(|| "---".to_string()),
+                        latest.version().to_string(),
+                        VersionChange::new(package_id.version(), latest.version()).to_string(),
+                    ],
+                ));
             }
             None => println!("Package {} not found", package_id.name()),
         }
     }
 
+    // Group the results by the workspace members that depend on each package
+    let mut is_first_group = true;
+    for (member, paths) in &member_paths {
+        let member_rows = rows_with_paths(&rows, paths);
+        let member_yanked_rows = rows_with_paths(&yanked_rows, paths);
+        if member_rows.is_empty() && member_yanked_rows.is_empty() {
+            continue;
+        }
+
+        if !is_first_group {
+            println!();
+        }
+        is_first_group = false;
+
+        if member_paths.len() > 1 {
+            println!("{}:", member.name());
+        }
+
+        if !member_rows.is_empty() {
+            utils::print_table(
+                &["Name", "Project", "Compat", "Latest", "Change", "Path"],
+                &member_rows,
+            );
+        }
+
+        if !member_yanked_rows.is_empty() {
+            if !member_rows.is_empty() {
+                println!();
+            }
+            println!("Yanked packages:");
+            utils::print_table(
+                &["Name", "Project", "Replacement", "Latest", "Path"],
+                &member_yanked_rows,
+            );
+        }
+    }
+
+    if has_yanked_without_replacement {
+        println!();
+        println!("Yanked packages without a replacement have no other version that satisfies their version requirements.");
+    }
+
+    if let Some(rust_version) = &rust_version {
+        if !rust_version_rows.is_empty() {
+            println!();
+            println!(
+                "Skipped versions that need a newer Rust version than {}:",
+                rust_version
+            );
+            utils::print_table(&["Name", "Version", "Rust"], &rust_version_rows);
+        }
+    }
+
+    drop(package_cache_lock);
+
+    if args.update {
+        update_manifests(&cargo_config, &args, &updates)?;
+    }
+
     if args.error && has_outdated_packages {
         anyhow::bail!("Found outdated packages");
     }
 
     Ok(())
 }
+
+/// Returns the rows for the packages that have a path, with the path added as the last column.
+/// Direct dependencies are shown as `direct`, while the path of a transitive dependency starts from the workspace member.
+fn rows_with_paths(rows: &[(PackageId, Vec<String>)], paths: &DependencyPaths) -> Vec<Vec<String>> {
+    rows.iter()
+        .filter_map(|(package_id, row)| {
+            let path = paths.get(package_id)?;
+            let path = if path.len() <= 2 {
+                "direct".to_string()
+            } else {
+                let names: Vec<String> = path.iter().map(|id| id.name().to_string()).collect();
+                names.join(" -> ")
+            };
+
+            let mut row = row.clone();
+            row.push(path);
+            Some(row)
+        })
+        .collect()
+}
+
+/// Rewrites the version requirements in the manifests of the selected workspace members,
+/// then updates `Cargo.lock` and downloads the new versions if requested
+fn update_manifests(
+    cargo_config: &CargoConfig,
+    args: &CargoSideloadOutdatedArgs,
+    updates: &[Update],
+) -> anyhow::Result<()> {
+    let workspace = utils::workspace(cargo_config, &args.common)?;
+    let members = utils::selected_members(&args.common, &workspace)?;
+
+    // Keep the original manifests so they can be put back if the new requirements don't resolve
+    let mut originals = Vec::new();
+
+    for member in workspace.members() {
+        if !members.contains(&member.package_id()) {
+            continue;
+        }
+
+        let manifest_path = member.manifest_path();
+        let contents = fs::read_to_string(manifest_path)?;
+        let (updated, changes) =
+            manifest::update_requirements(&contents, &args.common.registry, updates)?;
+
+        if changes.is_empty() {
+            continue;
+        }
+
+        fs::write(manifest_path, updated)?;
+        originals.push((manifest_path.to_path_buf(), contents));
+
+        for change in changes {
+            println!(
+                "Updated {} {} -> {} in {}",
+                change.name,
+                change.from,
+                change.to,
+                manifest_path.display()
+            );
+        }
+    }
+
+    if originals.is_empty() {
+        println!("No requirements to update");
+        return Ok(());
+    }
+
+    if !args.common.no_write_lockfile {
+        // Load the workspace again to pick up the new requirements
+        let resolved = utils::workspace(cargo_config, &args.common)
+            .and_then(|workspace| cargo::ops::resolve_ws(&workspace).map(|_| ()));
+
+        if let Err(e) = resolved {
+            for (path, contents) in &originals {
+                fs::write(path, contents)?;
+                println!("Restored {}", path.display());
+            }
+            return Err(e);
+        }
+    }
+
+    if args.fetch {
+        let mut common = args.common.clone();
+        // Use the lockfile that was just updated instead of generating a new one
+        common.no_write_lockfile = true;
+
+        super::fetch(CargoSideloadFetchArgs {
+            common,
+            crates: Vec::new(),
+            deps: false,
+            resolve: args.resolve.clone(),
+            headers: args.headers.clone(),
+            force: false,
+            dry_run: false,
+            require_signatures: args.require_signatures,
+            registry_config: args.registry_config.clone(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// The most significant part of the version number that changed between two versions.
+/// A breaking change is always major, even for `0.x` versions where it's the minor or patch number that changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VersionChange {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl VersionChange {
+    fn new(from: &Version, to: &Version) -> Self {
+        // Like Cargo, the leftmost non-zero number decides whether versions are compatible
+        let compatibility = |version: &Version| {
+            if version.major > 0 {
+                (version.major, 0, 0)
+            } else if version.minor > 0 {
+                (0, version.minor, 0)
+            } else {
+                (0, 0, version.patch)
+            }
+        };
+
+        if compatibility(from) != compatibility(to) {
+            VersionChange::Major
+        } else if from.minor != to.minor {
+            VersionChange::Minor
+        } else {
+            VersionChange::Patch
+        }
+    }
+}
+
+impl Display for VersionChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionChange::Patch => write!(f, "patch"),
+            VersionChange::Minor => write!(f, "minor"),
+            VersionChange::Major => write!(f, "major"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_change() {
+        let version = |v: &str| Version::parse(v).unwrap();
+
+        assert_eq!(
+            VersionChange::new(&version("1.2.3"), &version("1.2.4")),
+            VersionChange::Patch
+        );
+        assert_eq!(
+            VersionChange::new(&version("1.2.3"), &version("1.3.0")),
+            VersionChange::Minor
+        );
+        assert_eq!(
+            VersionChange::new(&version("1.2.3"), &version("2.0.0")),
+            VersionChange::Major
+        );
+        assert_eq!(
+            VersionChange::new(&version("1.0.0-beta.2"), &version("1.0.0-beta.5")),
+            VersionChange::Patch
+        );
+
+        // Breaking changes before 1.0.0
+        assert_eq!(
+            VersionChange::new(&version("0.1.0"), &version("0.1.1")),
+            VersionChange::Patch
+        );
+        assert_eq!(
+            VersionChange::new(&version("0.1.0"), &version("0.2.0")),
+            VersionChange::Major
+        );
+        assert_eq!(
+            VersionChange::new(&version("0.0.1"), &version("0.0.2")),
+            VersionChange::Major
+        );
+        assert_eq!(
+            VersionChange::new(&version("0.3.0"), &version("1.0.0")),
+            VersionChange::Major
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use cargo::{core::Verbosity, util::config::Config as CargoConfig};
+
+use crate::{api::RegistryApi, args::CargoSideloadOwnerArgs, utils};
+
+pub fn owner(args: CargoSideloadOwnerArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+    if args.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    if args.add.is_empty() && args.remove.is_empty() && !args.list {
+        anyhow::bail!("Use --add, --remove or --list to change or show the owners");
+    }
+
+    let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+    let api = RegistryApi::new(
+        &cargo_config,
+        &mut registry,
+        &args.registry,
+        args.headers.clone(),
+    )?;
+
+    if !args.add.is_empty() {
+        let message = api.change_owners(&args.name, &args.add, false)?;
+        println!(
+            "{}",
+            message.unwrap_or_else(|| format!(
+                "Added {} as owners of {}",
+                args.add.join(", "),
+                args.name
+            ))
+        );
+    }
+
+    if !args.remove.is_empty() {
+        let message = api.change_owners(&args.name, &args.remove, true)?;
+        println!(
+            "{}",
+            message.unwrap_or_else(|| format!(
+                "Removed {} as owners of {}",
+                args.remove.join(", "),
+                args.name
+            ))
+        );
+    }
+
+    if args.list {
+        let rows: Vec<Vec<String>> = api
+            .list_owners(&args.name)?
+            .into_iter()
+            .map(|owner| vec![owner.login, owner.name.unwrap_or_default()])
+            .collect();
+        utils::print_table(&["Login", "Name"], &rows);
+    }
+
+    Ok(())
+}
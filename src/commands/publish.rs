@@ -0,0 +1,219 @@
+use std::{collections::BTreeMap, io::Read};
+
+use cargo::{
+    core::{dependency::DepKind, source::Source, Package, SourceId, Verbosity, Workspace},
+    ops::PackageOpts,
+    util::config::Config as CargoConfig,
+};
+use crates_io::{NewCrate, NewCrateDependency};
+
+use crate::{api::RegistryApi, args::CargoSideloadPublishArgs, utils};
+
+pub fn publish(args: CargoSideloadPublishArgs) -> anyhow::Result<()> {
+    let mut cargo_config = CargoConfig::default()?;
+    utils::configure(&mut cargo_config, &args.common)?;
+    if args.common.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    // Cargo packages the current package of the workspace, so a member's workspace is loaded from its own manifest
+    let workspace = utils::workspace(&cargo_config, &args.common)?;
+    let manifest_path = match args.common.members.as_slice() {
+        [] => workspace.current()?.manifest_path().to_path_buf(),
+        [name] => match workspace
+            .members()
+            .find(|member| member.name().as_str() == name)
+        {
+            Some(member) => member.manifest_path().to_path_buf(),
+            None => anyhow::bail!("Package \"{}\" is not a member of the workspace", name),
+        },
+        _ => anyhow::bail!("Only one package can be published at a time"),
+    };
+    let workspace = Workspace::new(&manifest_path, &cargo_config)?;
+    let package = workspace.current()?;
+
+    let registry_name = &args.common.registry;
+    if let Some(allowed_registries) = package.publish() {
+        if !allowed_registries.contains(registry_name) {
+            anyhow::bail!(
+                "{} can't be published to {} because it isn't listed in the `publish` field of its Cargo.toml",
+                package.name(),
+                registry_name
+            );
+        }
+    }
+
+    verify_dependencies(package)?;
+
+    let mut registry = utils::create_registry(&cargo_config, registry_name)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+    let source_id = registry.source_id();
+    let api = RegistryApi::new(
+        &cargo_config,
+        &mut registry,
+        registry_name,
+        args.headers.clone(),
+    )?;
+
+    let tarball = cargo::ops::package(
+        &workspace,
+        &PackageOpts {
+            config: &cargo_config,
+            list: false,
+            check_metadata: true,
+            allow_dirty: args.allow_dirty,
+            verify: !args.no_verify,
+            jobs: None,
+            targets: Vec::new(),
+            features: Vec::new(),
+            all_features: false,
+            no_default_features: false,
+        },
+    )?;
+    let tarball = match tarball {
+        Some(tarball) => tarball,
+        None => anyhow::bail!("Failed to package {}", package.name()),
+    };
+    let new_crate = new_crate(&cargo_config, package, source_id)?;
+
+    if args.dry_run {
+        println!(
+            "{}-{} would be uploaded to {}/api/v1/crates/new",
+            package.name(),
+            package.version(),
+            api.api_url()
+        );
+        for header in &args.headers {
+            println!("Header {}", header.redacted());
+        }
+        return Ok(());
+    }
+
+    // The packaged file is renamed after it's written, so it's read through the open file rather than its path
+    let mut data = Vec::new();
+    tarball.file().read_to_end(&mut data)?;
+    let warnings = api.publish(&new_crate, &data)?;
+    for warning in warnings {
+        println!("Warning: {}", warning);
+    }
+    println!(
+        "Published {}-{} to {}",
+        package.name(),
+        package.version(),
+        registry_name
+    );
+
+    Ok(())
+}
+
+/// Checks that every path and git dependency also has a version, like `cargo publish` does.
+/// Dev-dependencies are exempt since the ones without a version are removed when the crate is packaged.
+pub fn verify_dependencies(package: &Package) -> anyhow::Result<()> {
+    for dep in package.dependencies() {
+        let source_id = dep.source_id();
+        if (source_id.is_path() || source_id.is_git())
+            && !dep.specified_req()
+            && dep.is_transitive()
+        {
+            anyhow::bail!(
+                "All dependencies must have a version specified when publishing, but `{}` only has a {}",
+                dep.package_name(),
+                if source_id.is_path() { "path" } else { "git repository" }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The metadata that's uploaded along with a packaged crate, the same as what `cargo publish` sends
+fn new_crate(
+    config: &CargoConfig,
+    package: &Package,
+    registry_id: SourceId,
+) -> anyhow::Result<NewCrate> {
+    let mut deps = Vec::new();
+    // Dev-dependencies without a version are removed when the crate is packaged
+    for dep in package
+        .dependencies()
+        .iter()
+        .filter(|dep| dep.is_transitive() || dep.specified_req())
+    {
+        // The web API uses no registry for dependencies from the same registry, while Cargo.toml uses it for crates.io
+        let dep_registry_id = match dep.registry_id() {
+            Some(id) => id,
+            None => SourceId::crates_io(config)?,
+        };
+        let registry = if dep_registry_id != registry_id {
+            Some(dep_registry_id.url().to_string())
+        } else {
+            None
+        };
+
+        deps.push(NewCrateDependency {
+            optional: dep.is_optional(),
+            default_features: dep.uses_default_features(),
+            name: dep.package_name().to_string(),
+            features: dep.features().iter().map(|s| s.to_string()).collect(),
+            version_req: dep.version_req().to_string(),
+            target: dep.platform().map(|platform| platform.to_string()),
+            kind: match dep.kind() {
+                DepKind::Normal => "normal",
+                DepKind::Build => "build",
+                DepKind::Development => "dev",
+            }
+            .to_string(),
+            registry,
+            explicit_name_in_toml: dep.explicit_name_in_toml().map(|name| name.to_string()),
+        });
+    }
+
+    let manifest = package.manifest();
+    let metadata = manifest.metadata();
+
+    let readme = match &metadata.readme {
+        Some(readme) => Some(std::fs::read_to_string(package.root().join(readme))?),
+        None => None,
+    };
+    if let Some(license_file) = &metadata.license_file {
+        if !package.root().join(license_file).exists() {
+            anyhow::bail!("The license file `{}` does not exist", license_file);
+        }
+    }
+
+    let features: BTreeMap<String, Vec<String>> = manifest
+        .original()
+        .features()
+        .map(|features| {
+            features
+                .iter()
+                .map(|(name, values)| {
+                    (
+                        name.to_string(),
+                        values.iter().map(|value| value.to_string()).collect(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(NewCrate {
+        name: package.name().to_string(),
+        vers: package.version().to_string(),
+        deps,
+        features,
+        authors: metadata.authors.clone(),
+        description: metadata.description.clone(),
+        documentation: metadata.documentation.clone(),
+        homepage: metadata.homepage.clone(),
+        readme,
+        readme_file: metadata.readme.clone(),
+        keywords: metadata.keywords.clone(),
+        categories: metadata.categories.clone(),
+        license: metadata.license.clone(),
+        license_file: metadata.license_file.clone(),
+        repository: metadata.repository.clone(),
+        badges: metadata.badges.clone(),
+        links: metadata.links.clone(),
+    })
+}
@@ -0,0 +1,885 @@
+use std::{
+    convert::TryInto,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+
+use log::debug;
+use semver::Version;
+use url::Url;
+
+use crate::{
+    args::{
+        CargoSideloadRegistryArgs, CargoSideloadRegistryCommand, CargoSideloadRegistryServeArgs,
+    },
+    config::Header,
+    manifest, utils,
+};
+
+pub fn registry(args: CargoSideloadRegistryArgs) -> anyhow::Result<()> {
+    match args.command {
+        CargoSideloadRegistryCommand::Serve(serve_args) => serve(&serve_args),
+    }
+}
+
+fn serve(args: &CargoSideloadRegistryServeArgs) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&args.address)?;
+    let server = Arc::new(RegistryServer::new(
+        &args.dir,
+        &listener,
+        args.headers.clone(),
+        args.max_upload_size,
+    )?);
+    server.update_index(&server.lock_index()?)?;
+
+    println!(
+        "Serving the registry in {} at {}",
+        args.dir.display(),
+        server.base_url
+    );
+    println!("Add it to your cargo config with either index:");
+    println!("  git:    {}", server.index_url);
+    println!("  sparse: sparse+{}/index/", server.base_url);
+    for header in &server.headers {
+        println!(
+            "Downloads and uploads require the header {}",
+            header.redacted()
+        );
+    }
+
+    server.run(listener)
+}
+
+/// Serves the index and `.crate` files of a registry from a directory
+struct RegistryServer {
+    index_dir: PathBuf,
+    crates_dir: PathBuf,
+    base_url: String,
+    index_url: String,
+    headers: Vec<Header>,
+    /// Largest request body that's read, in bytes
+    max_upload_size: usize,
+    /// Held while the index or the `.crate` files are changed, since uploads are handled on separate threads
+    index_lock: Mutex<()>,
+}
+
+/// Status, content type and body of an HTTP response
+type Response = (&'static str, &'static str, Vec<u8>);
+
+impl RegistryServer {
+    /// Creates a server for the registry in the directory, which is reached through the listener's address
+    fn new(
+        dir: &Path,
+        listener: &TcpListener,
+        headers: Vec<Header>,
+        max_upload_size: usize,
+    ) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let dir = fs::canonicalize(dir)?;
+        let index_dir = dir.join("index");
+        let index_url = match Url::from_file_path(&index_dir) {
+            Ok(url) => url,
+            Err(()) => anyhow::bail!("Invalid registry directory {}", dir.display()),
+        };
+
+        let crates_dir = dir.join("crates");
+        fs::create_dir_all(&crates_dir)?;
+
+        Ok(Self {
+            index_dir,
+            crates_dir,
+            base_url: format!("http://{}", listener.local_addr()?),
+            index_url: index_url.to_string(),
+            headers,
+            max_upload_size,
+            index_lock: Mutex::new(()),
+        })
+    }
+
+    /// Handles each connection to the listener on its own thread
+    fn run(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = Arc::clone(&self);
+            thread::spawn(move || {
+                if let Err(e) = server.handle(stream) {
+                    debug!("Request failed: {}", e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Locks the index and the `.crate` files until the guard is dropped
+    fn lock_index(&self) -> anyhow::Result<MutexGuard<'_, ()>> {
+        self.index_lock
+            .lock()
+            .map_err(|_| anyhow::anyhow!("The index lock is poisoned"))
+    }
+
+    /// Creates the git index if it doesn't exist, points its `config.json` at the server,
+    /// and adds any `.crate` files that aren't in the index yet
+    fn update_index(&self, _index_lock: &MutexGuard<'_, ()>) -> anyhow::Result<()> {
+        let base_url = &self.base_url;
+        let index_url = self.index_url.as_str();
+
+        let repo = match git2::Repository::open(&self.index_dir) {
+            Ok(repo) => repo,
+            // Cargo fetches the `master` branch of the index
+            Err(_) => git2::Repository::init_opts(
+                &self.index_dir,
+                git2::RepositoryInitOptions::new().initial_head("master"),
+            )?,
+        };
+
+        let mut messages = Vec::new();
+
+        let config = serde_json::json!({
+            "dl": format!("{}/api/v1/crates/{{crate}}/{{version}}/download", base_url),
+            "api": base_url,
+        });
+        let config_path = self.index_dir.join("config.json");
+        let config_json = serde_json::to_string_pretty(&config)?;
+        if fs::read_to_string(&config_path).ok().as_deref() != Some(config_json.as_str()) {
+            fs::write(&config_path, config_json)?;
+            messages.push(format!("Update config.json for {}", base_url));
+        }
+
+        let mut crate_files: Vec<PathBuf> = fs::read_dir(&self.crates_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        crate_files.retain(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "crate")
+        });
+        crate_files.sort();
+
+        for crate_file in crate_files {
+            let manifest = utils::crate_manifest(&crate_file)?;
+            let checksum = utils::file_checksum(&crate_file)?;
+            let entry = manifest::index_entry(&manifest, &checksum, index_url)?;
+            let name = entry["name"].as_str().unwrap_or_default().to_string();
+            let version = entry["vers"].as_str().unwrap_or_default().to_string();
+
+            let expected_file_name = format!("{}-{}.crate", name, version);
+            if crate_file.file_name() != Some(expected_file_name.as_ref()) {
+                anyhow::bail!(
+                    "{} should be named {}",
+                    crate_file.display(),
+                    expected_file_name
+                );
+            }
+
+            let index_path = self.index_dir.join(utils::index_file_path(&name));
+            let contents = fs::read_to_string(&index_path).unwrap_or_default();
+            let existing = contents
+                .lines()
+                .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+                .find(|existing| existing["vers"] == entry["vers"]);
+            if let Some(existing) = existing {
+                // A version can't change once it's in the index, since Cargo.lock files already have its checksum
+                if existing["cksum"] != entry["cksum"] {
+                    anyhow::bail!(
+                        "{} doesn't match the version that's already in the index: the index has checksum {} but the file has {}",
+                        crate_file.display(),
+                        existing["cksum"],
+                        checksum
+                    );
+                }
+                continue;
+            }
+
+            if let Some(parent) = index_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(
+                &index_path,
+                format!("{}{}\n", contents, serde_json::to_string(&entry)?),
+            )?;
+            println!("Added {}-{} to the index", name, version);
+            messages.push(format!("Add {}-{}", name, version));
+        }
+
+        if !messages.is_empty() {
+            commit(&repo, &format!("Update index\n\n{}", messages.join("\n")))?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) -> anyhow::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        let mut headers = Vec::new();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+            if let Ok(header) = Header::from_str(line.trim_end()) {
+                if header.name.eq_ignore_ascii_case("Content-Length") {
+                    content_length = header.value.trim().parse()?;
+                }
+                headers.push(header);
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+        let path = path.split('?').next().unwrap_or_default();
+
+        // Only uploads and owner changes have a body, and its size is limited so a request can't use up the server's memory
+        let (status, content_type, body) = if method != "PUT" && method != "DELETE" {
+            self.route(method, path, &headers, &[])
+        } else if content_length > self.max_upload_size {
+            error_response(
+                "413 Payload Too Large",
+                &format!(
+                    "The request is larger than the maximum of {} bytes",
+                    self.max_upload_size
+                ),
+            )
+        } else {
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body)?;
+            self.route(method, path, &headers, &request_body)
+        };
+        println!("{} {} {}", method, path, status);
+
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        stream.flush()?;
+
+        Ok(())
+    }
+
+    fn route(&self, method: &str, path: &str, headers: &[Header], body: &[u8]) -> Response {
+        if let Some(index_path) = path.strip_prefix("/index/") {
+            return match method {
+                "GET" => self.index_file(index_path),
+                _ => method_not_allowed(),
+            };
+        }
+
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match (method, segments.as_slice()) {
+            ("GET", ["api", "v1", "crates", name, version, "download"]) => {
+                self.download(name, version, headers)
+            }
+            ("PUT", ["api", "v1", "crates", "new"]) => self.publish(body, headers),
+            ("DELETE", ["api", "v1", "crates", name, version, "yank"]) => {
+                self.yank(name, version, true, headers)
+            }
+            ("PUT", ["api", "v1", "crates", name, version, "unyank"]) => {
+                self.yank(name, version, false, headers)
+            }
+            (_, ["api", "v1", "crates", _, "owners"]) => error_response(
+                "404 Not Found",
+                "The local registry doesn't keep track of owners",
+            ),
+            (_, ["api", "v1", "crates", _, _, "download"])
+            | (_, ["api", "v1", "crates", "new"])
+            | (_, ["api", "v1", "crates", _, _, "yank"])
+            | (_, ["api", "v1", "crates", _, _, "unyank"]) => method_not_allowed(),
+            _ => not_found(),
+        }
+    }
+
+    /// Serves a file of the sparse index. Hidden files like `.git` aren't served.
+    fn index_file(&self, path: &str) -> Response {
+        if path
+            .split('/')
+            .any(|segment| segment.is_empty() || segment.starts_with('.'))
+        {
+            return not_found();
+        }
+
+        match fs::read(self.index_dir.join(path)) {
+            Ok(body) if path == "config.json" => ("200 OK", "application/json", body),
+            Ok(body) => ("200 OK", "text/plain", body),
+            Err(_) => not_found(),
+        }
+    }
+
+    /// Serves a `.crate` file if the request has each of the required headers
+    fn download(&self, name: &str, version: &str, headers: &[Header]) -> Response {
+        if !self.is_authorized(headers) {
+            return ("401 Unauthorized", "text/plain", Vec::new());
+        }
+
+        if !is_valid_name(name) || Version::parse(version).is_err() {
+            return not_found();
+        }
+
+        let file_name = format!("{}-{}.crate", name, version);
+        match fs::read(self.crates_dir.join(file_name)) {
+            Ok(body) => ("200 OK", "application/octet-stream", body),
+            Err(_) => not_found(),
+        }
+    }
+
+    /// Adds an uploaded crate to the registry, the same request as `cargo publish` sends to `/api/v1/crates/new`
+    fn publish(&self, body: &[u8], headers: &[Header]) -> Response {
+        if !self.is_authorized(headers) {
+            return error_response("401 Unauthorized", "The required headers are missing");
+        }
+
+        let (metadata, tarball) = match parse_upload(body) {
+            Some(upload) => upload,
+            None => return error_response("400 Bad Request", "Invalid upload"),
+        };
+        let name = metadata["name"].as_str().unwrap_or_default();
+        let version = metadata["vers"].as_str().unwrap_or_default();
+        if !is_valid_name(name) || Version::parse(version).is_err() {
+            return error_response(
+                "400 Bad Request",
+                &format!("Invalid crate name or version: {} {}", name, version),
+            );
+        }
+
+        // The lock is held from the check until the crate is indexed, so two uploads of a version can't both be accepted
+        let index_lock = match self.lock_index() {
+            Ok(index_lock) => index_lock,
+            Err(e) => return error_response("500 Internal Server Error", &e.to_string()),
+        };
+        let crate_file = self.crates_dir.join(format!("{}-{}.crate", name, version));
+        if crate_file.exists() {
+            return error_response(
+                "409 Conflict",
+                &format!(
+                    "crate version `{}` of `{}` is already uploaded",
+                    version, name
+                ),
+            );
+        }
+
+        // The index entry is built from the uploaded `.crate` file rather than the metadata,
+        // which also checks that it's the crate and version it claims to be
+        let result = fs::write(&crate_file, tarball)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| self.update_index(&index_lock));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&crate_file);
+            return error_response("400 Bad Request", &e.to_string());
+        }
+
+        let response = serde_json::json!({
+            "warnings": {
+                "invalid_categories": [],
+                "invalid_badges": [],
+                "other": [],
+            }
+        });
+        (
+            "200 OK",
+            "application/json",
+            response.to_string().into_bytes(),
+        )
+    }
+
+    /// Sets the `yanked` flag of a version in the index
+    fn yank(&self, name: &str, version: &str, yanked: bool, headers: &[Header]) -> Response {
+        if !self.is_authorized(headers) {
+            return error_response("401 Unauthorized", "The required headers are missing");
+        }
+
+        let not_found = || {
+            error_response(
+                "404 Not Found",
+                &format!("crate `{}` does not have a version `{}`", name, version),
+            )
+        };
+        if !is_valid_name(name) || Version::parse(version).is_err() {
+            return not_found();
+        }
+
+        match self.set_yanked(name, version, yanked) {
+            Ok(true) => (
+                "200 OK",
+                "application/json",
+                serde_json::json!({ "ok": true }).to_string().into_bytes(),
+            ),
+            Ok(false) => not_found(),
+            Err(e) => error_response("500 Internal Server Error", &e.to_string()),
+        }
+    }
+
+    /// Rewrites the version's line in the index and commits it. Returns false if the version isn't in the index.
+    fn set_yanked(&self, name: &str, version: &str, yanked: bool) -> anyhow::Result<bool> {
+        let _index_lock = self.lock_index()?;
+
+        let index_path = self.index_dir.join(utils::index_file_path(name));
+        let contents = match fs::read_to_string(&index_path) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(false),
+        };
+
+        let mut is_found = false;
+        let mut is_changed = false;
+        let mut lines = Vec::new();
+        for line in contents.lines() {
+            match serde_json::from_str::<serde_json::Value>(line) {
+                Ok(mut entry) if entry["vers"] == version => {
+                    is_found = true;
+                    if entry["yanked"] != yanked {
+                        entry["yanked"] = yanked.into();
+                        is_changed = true;
+                    }
+                    lines.push(serde_json::to_string(&entry)?);
+                }
+                // Other versions are kept exactly as they are
+                _ => lines.push(line.to_string()),
+            }
+        }
+
+        if is_changed {
+            fs::write(&index_path, format!("{}\n", lines.join("\n")))?;
+            let action = if yanked { "Yank" } else { "Unyank" };
+            println!("{}ed {}-{} in the index", action, name, version);
+            commit(
+                &git2::Repository::open(&self.index_dir)?,
+                &format!("{} {}-{}", action, name, version),
+            )?;
+        }
+
+        Ok(is_found)
+    }
+
+    fn is_authorized(&self, headers: &[Header]) -> bool {
+        self.headers.iter().all(|required| {
+            headers.iter().any(|header| {
+                header.name.eq_ignore_ascii_case(&required.name) && header.value == required.value
+            })
+        })
+    }
+}
+
+/// Splits the body of an upload into the crate's metadata and its `.crate` file.
+/// Each is preceded by its length as a little-endian u32.
+fn parse_upload(body: &[u8]) -> Option<(serde_json::Value, &[u8])> {
+    fn take(body: &[u8]) -> Option<(&[u8], &[u8])> {
+        let length = u32::from_le_bytes(body.get(..4)?.try_into().ok()?) as usize;
+        let rest = &body[4..];
+        Some((rest.get(..length)?, &rest[length..]))
+    }
+
+    let (json, rest) = take(body)?;
+    let (tarball, _) = take(rest)?;
+    Some((serde_json::from_slice(json).ok()?, tarball))
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn not_found() -> Response {
+    ("404 Not Found", "text/plain", Vec::new())
+}
+
+fn method_not_allowed() -> Response {
+    ("405 Method Not Allowed", "text/plain", Vec::new())
+}
+
+/// An error in the format of the registry web API
+fn error_response(status: &'static str, detail: &str) -> Response {
+    let body = serde_json::json!({ "errors": [{ "detail": detail }] });
+    (status, "application/json", body.to_string().into_bytes())
+}
+
+/// Commits every change in the index's working directory
+fn commit(repo: &git2::Repository, message: &str) -> anyhow::Result<()> {
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let signature = git2::Signature::now("cargo-sideload", "cargo-sideload@localhost")?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(_) => None,
+    };
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &parents,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::RegistryApi, config::RegistryConfig, downloader::Downloader};
+    use cargo::{
+        core::{source::Source, Shell, Workspace},
+        util::config::Config as CargoConfig,
+    };
+    use crates_io::NewCrate;
+    use std::collections::BTreeMap;
+
+    /// A `.crate` file of an empty library, plus any extra files
+    fn crate_file(name: &str, version: &str, extra_files: &[(&str, &str)]) -> Vec<u8> {
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\n",
+            name, version
+        );
+        let mut files = vec![("Cargo.toml", manifest.as_str()), ("src/lib.rs", "")];
+        files.extend_from_slice(extra_files);
+
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(
+                    &mut header,
+                    format!("{}-{}/{}", name, version, path),
+                    contents.as_bytes(),
+                )
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    /// Serves the registry in `dir/registry` on a free port.
+    /// Returns the server and a Cargo config with its home in `dir/home` that has the registry as `local`.
+    fn start_server(
+        dir: &Path,
+        headers: Vec<Header>,
+        max_upload_size: usize,
+    ) -> (Arc<RegistryServer>, CargoConfig) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = Arc::new(
+            RegistryServer::new(&dir.join("registry"), &listener, headers, max_upload_size)
+                .unwrap(),
+        );
+        server.update_index(&server.lock_index().unwrap()).unwrap();
+        let running = Arc::clone(&server);
+        thread::spawn(move || running.run(listener));
+
+        let home = dir.join("home");
+        fs::create_dir_all(&home).unwrap();
+        fs::write(
+            home.join("config.toml"),
+            format!("[registries.local]\nindex = \"{}\"\n", server.index_url),
+        )
+        .unwrap();
+        let config = CargoConfig::new(Shell::new(), dir.to_path_buf(), home);
+
+        (server, config)
+    }
+
+    /// The metadata of a crate without dependencies
+    fn new_crate(name: &str, version: &str) -> NewCrate {
+        NewCrate {
+            name: name.to_string(),
+            vers: version.to_string(),
+            deps: Vec::new(),
+            features: BTreeMap::new(),
+            authors: Vec::new(),
+            description: None,
+            documentation: None,
+            homepage: None,
+            readme: None,
+            readme_file: None,
+            keywords: Vec::new(),
+            categories: Vec::new(),
+            license: None,
+            license_file: None,
+            repository: None,
+            badges: BTreeMap::new(),
+            links: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_upload() {
+        let json = br#"{"name":"my_lib","vers":"0.1.0"}"#;
+        let mut body = Vec::new();
+        body.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        body.extend_from_slice(json);
+        body.extend_from_slice(&3u32.to_le_bytes());
+        body.extend_from_slice(b"abc");
+
+        let (metadata, tarball) = parse_upload(&body).unwrap();
+        assert_eq!(metadata["name"], "my_lib");
+        assert_eq!(tarball, b"abc");
+
+        assert!(parse_upload(&body[..body.len() - 1]).is_none());
+        assert!(parse_upload(&[1, 0]).is_none());
+    }
+
+    #[test]
+    fn test_set_yanked() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = RegistryServer {
+            index_dir: dir.path().join("index"),
+            crates_dir: dir.path().join("crates"),
+            base_url: "http://127.0.0.1:8080".to_string(),
+            index_url: "file:///registry/index".to_string(),
+            headers: Vec::new(),
+            max_upload_size: 1024,
+            index_lock: Mutex::new(()),
+        };
+        fs::create_dir_all(&server.crates_dir).unwrap();
+        server.update_index(&server.lock_index().unwrap()).unwrap();
+
+        let index_path = server.index_dir.join("my").join("_l").join("my_lib");
+        fs::create_dir_all(index_path.parent().unwrap()).unwrap();
+        let other_version = r#"{"name":"my_lib","vers":"0.1.1","deps":[],"cksum":"b","features":{},"yanked":false}"#;
+        fs::write(
+            &index_path,
+            format!(
+                "{}\n{}\n",
+                r#"{"name":"my_lib","vers":"0.1.0","deps":[],"cksum":"a","features":{},"yanked":false}"#,
+                other_version
+            ),
+        )
+        .unwrap();
+
+        assert!(server.set_yanked("my_lib", "0.1.0", true).unwrap());
+        let lines: Vec<String> = fs::read_to_string(&index_path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        let entry: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(entry["yanked"], true);
+        assert_eq!(lines[1], other_version);
+
+        assert!(!server.set_yanked("my_lib", "0.2.0", true).unwrap());
+        assert!(!server.set_yanked("other_lib", "0.1.0", true).unwrap());
+    }
+
+    #[test]
+    fn test_serve_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let crates_dir = dir.path().join("registry").join("crates");
+        fs::create_dir_all(&crates_dir).unwrap();
+        let crate_path = crates_dir.join("my_lib-0.1.0.crate");
+        fs::write(&crate_path, crate_file("my_lib", "0.1.0", &[])).unwrap();
+
+        let headers = vec![Header::from_str("Authorization: abcd1234").unwrap()];
+        let (_server, config) = start_server(dir.path(), headers.clone(), 1024);
+
+        let mut registry = utils::create_registry(&config, "local").unwrap();
+        utils::update_index(&config, &mut registry).unwrap();
+        let summaries = utils::package_summaries(&config, &mut registry, "my_lib").unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            summaries[0].checksum(),
+            Some(utils::file_checksum(&crate_path).unwrap().as_str())
+        );
+
+        let mut downloader = Downloader::new(
+            &config,
+            "local",
+            Vec::new(),
+            &RegistryConfig::default(),
+            false,
+        )
+        .unwrap();
+        assert!(downloader.download("my_lib", "0.1.0").is_err());
+
+        let mut downloader =
+            Downloader::new(&config, "local", headers, &RegistryConfig::default(), false).unwrap();
+        let downloaded = downloader.download_crate("my_lib", "0.1.0").unwrap();
+        assert_eq!(
+            fs::read(downloaded).unwrap(),
+            fs::read(&crate_path).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_download_changed_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        let crates_dir = dir.path().join("registry").join("crates");
+        fs::create_dir_all(&crates_dir).unwrap();
+        let crate_path = crates_dir.join("my_lib-0.1.0.crate");
+        fs::write(&crate_path, crate_file("my_lib", "0.1.0", &[])).unwrap();
+
+        let (_server, config) = start_server(dir.path(), Vec::new(), 1024);
+        let mut registry = utils::create_registry(&config, "local").unwrap();
+        utils::update_index(&config, &mut registry).unwrap();
+
+        // The server sends a file that doesn't match the checksum in the index
+        fs::write(
+            &crate_path,
+            crate_file("my_lib", "0.1.0", &[("README.md", "")]),
+        )
+        .unwrap();
+
+        let mut downloader = Downloader::new(
+            &config,
+            "local",
+            Vec::new(),
+            &RegistryConfig::default(),
+            false,
+        )
+        .unwrap();
+        assert!(downloader.download("my_lib", "0.1.0").is_err());
+        assert!(downloader.fetch("my_lib", "0.1.0").is_err());
+
+        let registry_directory = utils::registry_directory(registry.source_id());
+        let cache_dir = config
+            .registry_cache_path()
+            .join(&registry_directory)
+            .into_path_unlocked();
+        assert!(!cache_dir.join("my_lib-0.1.0.crate").exists());
+        let source_dir = config
+            .registry_source_path()
+            .join(&registry_directory)
+            .into_path_unlocked();
+        assert!(!source_dir.join("my_lib-0.1.0").exists());
+    }
+
+    #[test]
+    fn test_update_index_changed_crate() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = RegistryServer::new(dir.path(), &listener, Vec::new(), 1024).unwrap();
+        let crate_path = server.crates_dir.join("my_lib-0.1.0.crate");
+        fs::write(&crate_path, crate_file("my_lib", "0.1.0", &[])).unwrap();
+        server.update_index(&server.lock_index().unwrap()).unwrap();
+
+        // Serving the same version with different contents would break every Cargo.lock that has it
+        fs::write(
+            &crate_path,
+            crate_file("my_lib", "0.1.0", &[("README.md", "")]),
+        )
+        .unwrap();
+        assert!(server.update_index(&server.lock_index().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_publish() {
+        let dir = tempfile::tempdir().unwrap();
+        let headers = vec![Header::from_str("Authorization: abcd1234").unwrap()];
+        let (server, config) = start_server(dir.path(), headers.clone(), 4096);
+
+        let mut registry = utils::create_registry(&config, "local").unwrap();
+        utils::update_index(&config, &mut registry).unwrap();
+        let api = RegistryApi::new(&config, &mut registry, "local", headers).unwrap();
+        let unauthorized = RegistryApi::new(&config, &mut registry, "local", Vec::new()).unwrap();
+        let tarball = crate_file("my_lib", "0.1.0", &[]);
+
+        // Uploads without the headers, with metadata for another crate, or over the size limit are rejected
+        assert!(unauthorized
+            .publish(&new_crate("my_lib", "0.1.0"), &tarball)
+            .is_err());
+        assert!(api
+            .publish(&new_crate("other_lib", "0.1.0"), &tarball)
+            .is_err());
+        let large = NewCrate {
+            readme: Some("a".repeat(4096)),
+            ..new_crate("my_lib", "0.1.0")
+        };
+        assert!(api.publish(&large, &tarball).is_err());
+        assert!(fs::read_dir(&server.crates_dir).unwrap().next().is_none());
+
+        assert!(api
+            .publish(&new_crate("my_lib", "0.1.0"), &tarball)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            fs::read(server.crates_dir.join("my_lib-0.1.0.crate")).unwrap(),
+            tarball
+        );
+        assert!(api
+            .publish(&new_crate("my_lib", "0.1.0"), &tarball)
+            .is_err());
+
+        let index_file = server.index_dir.join(utils::index_file_path("my_lib"));
+        let entry: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(index_file).unwrap()).unwrap();
+        assert_eq!(entry["vers"], "0.1.0");
+        assert_eq!(
+            entry["cksum"],
+            cargo::util::Sha256::new().update(&tarball).finish_hex()
+        );
+    }
+
+    #[test]
+    fn test_verify_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = CargoConfig::new(
+            Shell::new(),
+            dir.path().to_path_buf(),
+            dir.path().join("home"),
+        );
+        fs::create_dir_all(dir.path().join("core_lib/src")).unwrap();
+        fs::write(
+            dir.path().join("core_lib/Cargo.toml"),
+            "[package]\nname = \"core_lib\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("core_lib/src/lib.rs"), "").unwrap();
+
+        // Loads a package with the given dependency tables and checks its dependencies
+        let verify = |name: &str, dependencies: &str| {
+            let root = dir.path().join(name);
+            fs::create_dir_all(root.join("src")).unwrap();
+            fs::write(root.join("src/lib.rs"), "").unwrap();
+            fs::write(
+                root.join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n{}",
+                    name, dependencies
+                ),
+            )
+            .unwrap();
+            let workspace = Workspace::new(&root.join("Cargo.toml"), &config).unwrap();
+            crate::commands::publish::verify_dependencies(workspace.current().unwrap())
+        };
+
+        assert!(verify(
+            "path_dep",
+            "[dependencies]\ncore_lib = { path = \"../core_lib\" }\n"
+        )
+        .is_err());
+        assert!(verify(
+            "git_dep",
+            "[build-dependencies]\nother_lib = { git = \"https://example.com/other_lib\" }\n"
+        )
+        .is_err());
+        assert!(verify(
+            "versioned_dep",
+            "[dependencies]\ncore_lib = { path = \"../core_lib\", version = \"0.1.0\" }\n"
+        )
+        .is_ok());
+        assert!(verify(
+            "dev_dep",
+            "[dev-dependencies]\ncore_lib = { path = \"../core_lib\" }\n"
+        )
+        .is_ok());
+    }
+}
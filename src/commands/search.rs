@@ -0,0 +1,128 @@
+use cargo::{
+    core::{source::Source, Verbosity},
+    util::config::Config as CargoConfig,
+};
+use regex::RegexBuilder;
+use semver::Version;
+
+use crate::{args::CargoSideloadSearchArgs, utils};
+
+pub fn search(args: CargoSideloadSearchArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+    if args.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+
+    // Crate names in the index are lowercase
+    let pattern = args.pattern.to_lowercase();
+    let regex = if args.regex {
+        Some(
+            RegexBuilder::new(&args.pattern)
+                .case_insensitive(true)
+                .build()?,
+        )
+    } else {
+        None
+    };
+
+    let is_match = |name: &str| match &regex {
+        Some(regex) => regex.is_match(name),
+        None if args.prefix => name.starts_with(&pattern),
+        None => name.contains(&pattern),
+    };
+
+    let index = utils::LocalIndex::open(&cargo_config, registry.source_id())?;
+    let mut rows = Vec::new();
+    for name in index.package_names()? {
+        if !is_match(&name) {
+            continue;
+        }
+
+        let entries = index.entries(&name)?;
+        if let Some((name, version)) = latest_entry(&entries) {
+            rows.push(vec![name, version.to_string()]);
+        }
+    }
+
+    if rows.is_empty() {
+        println!("No crates found");
+        return Ok(());
+    }
+
+    let total = rows.len();
+    if let Some(limit) = args.limit {
+        rows.truncate(limit);
+    }
+
+    utils::print_table(&["Name", "Latest"], &rows);
+    if rows.len() < total {
+        println!("... and {} more crates", total - rows.len());
+    }
+
+    Ok(())
+}
+
+/// Returns the name of the crate and its latest version that isn't yanked.
+/// Pre-releases are only used if the crate doesn't have any other versions.
+fn latest_entry(entries: &[serde_json::Value]) -> Option<(String, Version)> {
+    let mut name = None;
+    let mut versions = Vec::new();
+
+    for entry in entries {
+        if entry.get("yanked").and_then(serde_json::Value::as_bool) == Some(true) {
+            continue;
+        }
+
+        let version = entry
+            .get("vers")
+            .and_then(serde_json::Value::as_str)
+            .and_then(|version| Version::parse(version).ok());
+        if let Some(version) = version {
+            versions.push(version);
+            name = entry.get("name").and_then(serde_json::Value::as_str);
+        }
+    }
+
+    let latest = versions
+        .iter()
+        .filter(|version| !version.is_prerelease())
+        .max()
+        .or_else(|| versions.iter().max())?;
+
+    Some((name?.to_string(), latest.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_entry() {
+        let entry = |version: &str, yanked: bool| serde_json::json!({ "name": "My_Lib", "vers": version, "yanked": yanked });
+
+        let entries = vec![
+            entry("0.1.0", false),
+            entry("0.2.0", false),
+            entry("0.3.0", true),
+            entry("1.0.0-beta.1", false),
+        ];
+        assert_eq!(
+            latest_entry(&entries),
+            Some(("My_Lib".to_string(), Version::parse("0.2.0").unwrap()))
+        );
+
+        let entries = vec![entry("1.0.0-beta.1", false), entry("1.0.0-beta.2", true)];
+        assert_eq!(
+            latest_entry(&entries),
+            Some((
+                "My_Lib".to_string(),
+                Version::parse("1.0.0-beta.1").unwrap()
+            ))
+        );
+
+        assert_eq!(latest_entry(&[entry("0.1.0", true)]), None);
+    }
+}
@@ -0,0 +1,240 @@
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use cargo::{
+    core::{source::Source, PackageId, Verbosity},
+    util::config::Config as CargoConfig,
+};
+use semver::{Version, VersionReq};
+
+use crate::{
+    args::CargoSideloadVerifyArgs,
+    downloader::Downloader,
+    utils::{self, CachedCrate},
+};
+
+pub fn verify(args: CargoSideloadVerifyArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+    if args.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+    let source_id = registry.source_id();
+
+    // The checksum of each version in the lockfiles
+    let mut lockfiles: Vec<PathBuf> = args
+        .lockfiles
+        .iter()
+        .map(|path| {
+            if path.is_dir() {
+                path.join("Cargo.lock")
+            } else {
+                path.clone()
+            }
+        })
+        .collect();
+    if lockfiles.is_empty() && PathBuf::from("Cargo.lock").is_file() {
+        lockfiles.push(PathBuf::from("Cargo.lock"));
+    }
+    let mut locked_checksums = HashMap::new();
+    for path in &lockfiles {
+        for package in utils::lockfile_packages(path, source_id)? {
+            if let Some(checksum) = package.checksum {
+                locked_checksums
+                    .entry((package.name, package.version))
+                    .or_insert_with(Vec::new)
+                    .push(checksum);
+            }
+        }
+    }
+
+    let package_cache_lock = cargo_config.acquire_package_cache_lock()?;
+
+    let cached_crates = utils::cached_crates(&cargo_config, source_id)?;
+    if cached_crates.is_empty() {
+        println!("No crates cached for {}", args.registry);
+        return Ok(());
+    }
+
+    let mut index_checksums = HashMap::new();
+    let mut rows = Vec::new();
+    let mut failed = Vec::new();
+    for cached_crate in &cached_crates {
+        if !index_checksums.contains_key(&cached_crate.name) {
+            let summaries = utils::package_summaries_with_yanked(
+                &cargo_config,
+                &mut registry,
+                &cached_crate.name,
+                &VersionReq::any(),
+            )?;
+            let checksums: HashMap<Version, Option<String>> = summaries
+                .iter()
+                .map(|summary| {
+                    (
+                        summary.version().clone(),
+                        summary.checksum().map(String::from),
+                    )
+                })
+                .collect();
+            index_checksums.insert(cached_crate.name.clone(), checksums);
+        }
+        let index_checksum = index_checksums[&cached_crate.name].get(&cached_crate.version);
+
+        let status = CrateStatus::new(
+            cached_crate,
+            index_checksum,
+            locked_checksums.get(&(cached_crate.name.clone(), cached_crate.version.clone())),
+        )?;
+
+        if !status.is_ok() {
+            failed.push(cached_crate);
+        }
+        rows.push(vec![
+            cached_crate.name.clone(),
+            cached_crate.version.to_string(),
+            status.index.to_string(),
+            status.lockfile.to_string(),
+            status.source.to_string(),
+        ]);
+    }
+
+    utils::print_table(&["Name", "Version", "Index", "Lockfile", "Source"], &rows);
+
+    drop(package_cache_lock);
+
+    if failed.is_empty() {
+        println!("{} cached versions verified", cached_crates.len());
+        return Ok(());
+    }
+
+    if !args.redownload {
+        anyhow::bail!(
+            "{} of {} cached versions failed verification. Use --redownload to download them again.",
+            failed.len(),
+            cached_crates.len()
+        );
+    }
+
+    let mut downloader = Downloader::new(
+        &cargo_config,
+        &args.registry,
+        args.headers.clone(),
+        &args.registry_config,
+        false,
+    )?;
+    // Downloads are checked against the lockfiles and the index before they're cached,
+    // so a version that still doesn't match is left out of the cache instead of being downloaded again
+    for path in &lockfiles {
+        downloader.read_locked_checksums(path)?;
+    }
+    for cached_crate in failed {
+        let version = cached_crate.version.to_string();
+        let package_id = PackageId::new(&cached_crate.name, &version, source_id)?;
+
+        downloader.delete_existing(package_id)?;
+        downloader.download(&cached_crate.name, &version)?;
+    }
+
+    Ok(())
+}
+
+/// The result of each check for a cached crate
+struct CrateStatus {
+    index: ChecksumStatus,
+    lockfile: ChecksumStatus,
+    source: SourceStatus,
+}
+
+impl CrateStatus {
+    /// Compares the `.crate` file against the checksums from the index and lockfiles,
+    /// and the unpacked source against the `.crate` file
+    fn new(
+        cached_crate: &CachedCrate,
+        index_checksum: Option<&Option<String>>,
+        locked_checksums: Option<&Vec<String>>,
+    ) -> anyhow::Result<Self> {
+        let checksum = utils::file_checksum(&cached_crate.crate_file)?;
+
+        let index = match index_checksum {
+            Some(Some(expected)) if *expected == checksum => ChecksumStatus::Ok,
+            Some(Some(_)) => ChecksumStatus::Mismatch,
+            Some(None) => ChecksumStatus::NoChecksum,
+            None => ChecksumStatus::Missing,
+        };
+
+        let lockfile = match locked_checksums {
+            Some(expected) if expected.iter().all(|expected| *expected == checksum) => {
+                ChecksumStatus::Ok
+            }
+            Some(_) => ChecksumStatus::Mismatch,
+            None => ChecksumStatus::NoChecksum,
+        };
+
+        // The source isn't unpacked until the crate is used, so a missing source directory is fine
+        let source = if !cached_crate.source_dir.is_dir() {
+            SourceStatus::NotUnpacked
+        } else {
+            match utils::modified_files(&cached_crate.crate_file, &cached_crate.source_dir) {
+                Ok(modified) if modified.is_empty() => SourceStatus::Ok,
+                Ok(modified) => SourceStatus::Modified(modified.len()),
+                Err(_) => SourceStatus::Unreadable,
+            }
+        };
+
+        Ok(Self {
+            index,
+            lockfile,
+            source,
+        })
+    }
+
+    fn is_ok(&self) -> bool {
+        self.index != ChecksumStatus::Mismatch
+            && self.lockfile != ChecksumStatus::Mismatch
+            && matches!(self.source, SourceStatus::Ok | SourceStatus::NotUnpacked)
+    }
+}
+
+/// How the checksum of a `.crate` file compares to the expected checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChecksumStatus {
+    Ok,
+    Mismatch,
+    /// There's no checksum to compare against
+    NoChecksum,
+    /// The version isn't in the registry index
+    Missing,
+}
+
+impl fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumStatus::Ok => write!(f, "ok"),
+            ChecksumStatus::Mismatch => write!(f, "mismatch"),
+            ChecksumStatus::NoChecksum => write!(f, "---"),
+            ChecksumStatus::Missing => write!(f, "missing"),
+        }
+    }
+}
+
+/// How the unpacked source compares to the `.crate` file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceStatus {
+    Ok,
+    NotUnpacked,
+    /// The number of files that were changed or removed
+    Modified(usize),
+    Unreadable,
+}
+
+impl fmt::Display for SourceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceStatus::Ok => write!(f, "ok"),
+            SourceStatus::NotUnpacked => write!(f, "---"),
+            SourceStatus::Modified(count) => write!(f, "{} modified", count),
+            SourceStatus::Unreadable => write!(f, "unreadable"),
+        }
+    }
+}
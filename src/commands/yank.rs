@@ -0,0 +1,72 @@
+use cargo::{
+    core::{source::Source, Verbosity},
+    util::config::Config as CargoConfig,
+};
+use semver::Version;
+
+use crate::{api::RegistryApi, args::CargoSideloadYankArgs, utils};
+
+pub fn yank(args: CargoSideloadYankArgs) -> anyhow::Result<()> {
+    let cargo_config = CargoConfig::default()?;
+    if args.quiet {
+        cargo_config.shell().set_verbosity(Verbosity::Quiet);
+    }
+
+    let name = &args.crate_spec.name;
+    let req = &args.crate_spec.req;
+
+    let mut registry = utils::create_registry(&cargo_config, &args.registry)?;
+    utils::update_index(&cargo_config, &mut registry)?;
+
+    let summaries = utils::package_summaries_with_yanked(&cargo_config, &mut registry, name, req)?;
+    let mut versions: Vec<&Version> = summaries
+        .iter()
+        .map(|summary| summary.version())
+        .filter(|version| req.matches(version))
+        .collect();
+    versions.sort();
+    versions.dedup();
+
+    // Yanking is done one version at a time, so a requirement like `0.1` can't be used to yank several versions
+    let version = match versions.as_slice() {
+        [] => anyhow::bail!("Package {} matching `{}` not found", name, req),
+        [version] => version.to_string(),
+        _ => anyhow::bail!(
+            "`{}` matches more than one version of {}: {}. Use an exact version like {}@{}",
+            req,
+            name,
+            versions
+                .iter()
+                .map(|version| version.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            name,
+            versions[versions.len() - 1]
+        ),
+    };
+
+    let is_yanked = utils::yanked_versions(&cargo_config, registry.source_id(), name)?
+        .iter()
+        .any(|yanked| yanked.to_string() == version);
+    if is_yanked != args.undo {
+        let state = if is_yanked { "yanked" } else { "not yanked" };
+        println!("{}-{} is already {}", name, version, state);
+        return Ok(());
+    }
+
+    let api = RegistryApi::new(
+        &cargo_config,
+        &mut registry,
+        &args.registry,
+        args.headers.clone(),
+    )?;
+    api.yank(name, &version, args.undo)?;
+
+    if args.undo {
+        println!("Unyanked {}-{} in {}", name, version, args.registry);
+    } else {
+        println!("Yanked {}-{} from {}", name, version, args.registry);
+    }
+
+    Ok(())
+}
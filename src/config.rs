@@ -31,9 +31,16 @@ impl Config {
 }
 
 /// Configuration for an individual registry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RegistryConfig {
+    #[serde(default)]
     pub default_headers: Vec<Header>,
+    /// Base64 minisign public keys that are trusted to sign the registry's crates
+    #[serde(default)]
+    pub trusted_keys: Vec<String>,
+    /// Url of each crate's signature, with the same markers as the index's `dl` url and `{url}` for the crate's download url.
+    /// Defaults to `{url}.minisig`.
+    pub signature_url: Option<String>,
 }
 
 /// Header name and value with the string representation `[Header-Name]: [Header Value]`
@@ -45,6 +52,13 @@ pub struct Header {
     pub value: String,
 }
 
+impl Header {
+    /// String representation of the header with its value hidden
+    pub fn redacted(&self) -> String {
+        format!("{}: <redacted>", self.name)
+    }
+}
+
 impl FromStr for Header {
     type Err = anyhow::Error;
 
@@ -96,6 +110,12 @@ mod tests {
         assert!(Header::from_str("").is_err());
     }
 
+    #[test]
+    fn test_header_redacted() {
+        let header = Header::from_str("Authorization: Bearer abcd1234").unwrap();
+        assert_eq!(header.redacted(), "Authorization: <redacted>");
+    }
+
     #[test]
     fn test_config_full() {
         let config_str = r#"
@@ -176,6 +196,25 @@ mod tests {
         assert_eq!(second_header.value, "And its value");
     }
 
+    #[test]
+    fn test_config_with_signatures() {
+        let config_str = r#"
+            [registries.test_registry]
+            trusted_keys = [ "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3" ]
+            signature_url = "https://example.com/signatures/{crate}-{version}.minisig"
+        "#;
+
+        let config: Config = toml::from_str(config_str).unwrap();
+
+        let test_registry_config = config.registries.get("test_registry").unwrap();
+        assert!(test_registry_config.default_headers.is_empty());
+        assert_eq!(test_registry_config.trusted_keys.len(), 1);
+        assert_eq!(
+            test_registry_config.signature_url.as_deref(),
+            Some("https://example.com/signatures/{crate}-{version}.minisig")
+        );
+    }
+
     #[test]
     fn test_config_empty() {
         let config_str = "";
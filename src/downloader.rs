@@ -0,0 +1,469 @@
+use cargo::{
+    core::{
+        package_id::PackageId,
+        source::{MaybePackage, Source},
+    },
+    sources::registry::RegistrySource,
+    util::{config::Config as CargoConfig, Filesystem},
+};
+use log::debug;
+use semver::VersionReq;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{Header, RegistryConfig},
+    signature::TrustedKeys,
+    utils,
+};
+
+/// Downloads packages from a particular registry
+pub struct Downloader<'cfg> {
+    config: &'cfg CargoConfig,
+    registry: RegistrySource<'cfg>,
+    client: reqwest::blocking::Client,
+    headers: Vec<Header>,
+    force: bool,
+    locked_checksums: HashMap<PackageId, String>,
+    trusted_keys: TrustedKeys,
+    signature_url: String,
+    require_signatures: bool,
+}
+
+impl<'cfg> Downloader<'cfg> {
+    /// Creates a downloader that adds the headers to each request.
+    /// If `force` is set, packages are downloaded again even if they're already cached.
+    /// Signatures are verified if the registry's config has trusted keys.
+    pub fn new(
+        config: &'cfg CargoConfig,
+        registry_name: &str,
+        headers: Vec<Header>,
+        registry_config: &RegistryConfig,
+        force: bool,
+    ) -> anyhow::Result<Self> {
+        let registry = utils::create_registry(config, registry_name)?;
+        let client = reqwest::blocking::Client::new();
+
+        let trusted_keys = TrustedKeys::new(&registry_config.trusted_keys)?;
+        let signature_url = registry_config
+            .signature_url
+            .clone()
+            .unwrap_or_else(|| "{url}.minisig".to_string());
+
+        Ok(Self {
+            config,
+            registry,
+            client,
+            headers,
+            force,
+            locked_checksums: HashMap::new(),
+            trusted_keys,
+            signature_url,
+            require_signatures: false,
+        })
+    }
+
+    /// Fails downloads that don't have a valid signature, including packages that are already cached
+    pub fn require_signatures(&mut self) -> anyhow::Result<()> {
+        if self.trusted_keys.is_empty() {
+            anyhow::bail!("Signatures can't be required because the registry has no `trusted_keys` in your cargo-sideload config");
+        }
+
+        self.require_signatures = true;
+        Ok(())
+    }
+
+    /// Reads the checksums from a `Cargo.lock` file that downloaded packages are checked against,
+    /// in addition to the checksums in the registry index
+    pub fn read_locked_checksums(&mut self, lockfile: &Path) -> anyhow::Result<()> {
+        let source_id = self.registry.source_id();
+        for package in utils::lockfile_packages(lockfile, source_id)? {
+            if let Some(checksum) = package.checksum {
+                let package_id = PackageId::new(&package.name, &package.version, source_id)?;
+                self.locked_checksums.insert(package_id, checksum);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Download the specified version of a package.
+    pub fn download(&mut self, name: &str, version: &str) -> anyhow::Result<()> {
+        let source_id = self.registry.source_id();
+        let package_id = PackageId::new(name, version, source_id)?;
+
+        if self.force {
+            self.delete_existing(package_id)?;
+        }
+
+        match self.package_status(package_id)? {
+            MaybePackage::Ready(_) => {
+                println!(
+                    "{}-{} is already cached.",
+                    package_id.name(),
+                    package_id.version()
+                );
+
+                let file_name = format!("{}-{}.crate", package_id.name(), package_id.version());
+                let file_path = self.target_dir().join(file_name).into_path_unlocked();
+
+                // Cached packages may have been downloaded before signatures were required
+                if self.require_signatures {
+                    let url = self.download_url(package_id)?;
+                    self.check_signature(package_id, &url, &std::fs::read(&file_path)?)
+                        .map_err(|e| {
+                            e.context(format!(
+                                "{}-{} is cached but its signature couldn't be verified. Use --force to download it again.",
+                                package_id.name(),
+                                package_id.version()
+                            ))
+                        })?;
+                }
+
+                self.check_checksums(package_id, utils::file_checksum(&file_path)?)
+                    .map_err(|e| {
+                        e.context(format!(
+                            "{}-{} is cached but doesn't match the expected checksum. Use --force to download it again.",
+                            package_id.name(),
+                            package_id.version()
+                        ))
+                    })?;
+            }
+            MaybePackage::Download { url, .. } => self.download_package(package_id, &url)?,
+        }
+
+        Ok(())
+    }
+
+    /// Downloads the specified version of a package and returns the contents of its `.crate` file.
+    /// The file is verified the same way as in `download`, but it isn't added to the cache.
+    pub fn fetch(&mut self, name: &str, version: &str) -> anyhow::Result<Vec<u8>> {
+        let package_id = PackageId::new(name, version, self.registry.source_id())?;
+        let url = self.download_url(package_id)?;
+        self.fetch_package(package_id, &url)
+    }
+
+    /// Downloads the specified version of a package if needed and returns the path to its `.crate` file in the cache
+    pub fn download_crate(&mut self, name: &str, version: &str) -> anyhow::Result<PathBuf> {
+        self.download(name, version)?;
+
+        let package_id = PackageId::new(name, version, self.registry.source_id())?;
+        let file_name = format!("{}-{}.crate", package_id.name(), package_id.version());
+        Ok(self.target_dir().join(file_name).into_path_unlocked())
+    }
+
+    /// Prints what `download` would do for the specified version of a package.
+    /// Nothing is downloaded and the cache is left untouched.
+    pub fn plan(&mut self, name: &str, version: &str) -> anyhow::Result<()> {
+        let source_id = self.registry.source_id();
+        let package_id = PackageId::new(name, version, source_id)?;
+
+        if !self.force && self.is_cached(package_id)? {
+            println!(
+                "{}-{} is already cached.",
+                package_id.name(),
+                package_id.version()
+            );
+            return Ok(());
+        }
+
+        let url = self.download_url(package_id)?;
+        println!(
+            "{}-{} would be downloaded from {}",
+            package_id.name(),
+            package_id.version(),
+            url
+        );
+
+        Ok(())
+    }
+
+    /// Checks if the .crate file is already in the cache without unpacking it.
+    fn is_cached(&self, package_id: PackageId) -> anyhow::Result<bool> {
+        let _package_cache_lock = self.config.acquire_package_cache_lock()?;
+
+        let file_name = format!("{}-{}.crate", package_id.name(), package_id.version());
+        let file_path = self.target_dir().join(file_name).into_path_unlocked();
+
+        match std::fs::metadata(file_path) {
+            Ok(metadata) => Ok(metadata.len() > 0),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Resolves the download url of a package from the registry's `config.json`
+    fn download_url(&mut self, package_id: PackageId) -> anyhow::Result<String> {
+        let _package_cache_lock = self.config.acquire_package_cache_lock()?;
+
+        match self.registry.config()? {
+            Some(registry_config) => Ok(utils::download_url(&registry_config, package_id)),
+            None => anyhow::bail!("No download url available for {}", package_id.name()),
+        }
+    }
+
+    /// Checks if the .crate file is already in the cache. If it is, it will also be unpacked by Cargo.
+    fn package_status(&mut self, package_id: PackageId) -> anyhow::Result<MaybePackage> {
+        let _package_cache_lock = self.config.acquire_package_cache_lock()?;
+        // This method won't actually start a download.
+        // If the .crate file is already in the cache it'll unpack it, otherwise it will return the download url
+        let result = self.registry.download(package_id);
+
+        if result.is_err() {
+            println!(
+                "Failed to unpack crate file for {}. Double check your download url and headers.",
+                package_id.name()
+            );
+            self.delete_existing(package_id)?;
+        }
+
+        result
+    }
+
+    /// Downloads a `.crate` file and checks its signature and checksums
+    fn fetch_package(&mut self, package_id: PackageId, url: &str) -> anyhow::Result<Vec<u8>> {
+        debug!("Downloading: {}", url);
+
+        let response = self.get(url)?;
+        let body = response.error_for_status()?.bytes()?.to_vec();
+        debug!("BODY");
+        debug!("{}", String::from_utf8_lossy(&body));
+
+        self.check_signature(package_id, url, &body)?;
+        let checksum = cargo::util::Sha256::new().update(&body).finish_hex();
+        self.check_checksums(package_id, checksum)?;
+
+        Ok(body)
+    }
+
+    /// Perform the actual download.
+    /// Packages that fail the signature or checksum checks never make it into the cache for Cargo to build with.
+    fn download_package(&mut self, package_id: PackageId, url: &str) -> anyhow::Result<()> {
+        let body = self.fetch_package(package_id, url)?;
+
+        let file_name = format!("{}-{}.crate", package_id.name(), package_id.version());
+
+        {
+            let file_lock =
+                self.target_dir()
+                    .open_rw(file_name, self.config, "Waiting for file lock...")?;
+
+            let file_path = file_lock.path();
+
+            std::fs::write(file_path, body)?;
+            println!(
+                "{}-{} added to package cache",
+                package_id.name(),
+                package_id.version()
+            );
+            debug!("Download path: {:?}", file_path);
+        }
+
+        // The code to unpack the crate is private, but we can trigger it by calling `Source::download` again.
+        // This will see that the cached file is already present and attempt to unpack it.
+        self.package_status(package_id)?;
+
+        Ok(())
+    }
+
+    /// Sends a GET request with the headers
+    fn get(&self, url: &str) -> anyhow::Result<reqwest::blocking::Response> {
+        let mut request_builder = self.client.get(url);
+
+        for header in &self.headers {
+            request_builder = request_builder.header(&header.name, &header.value);
+        }
+
+        let request = request_builder.build()?;
+        debug!("{:#?}", request);
+
+        let response = self.client.execute(request)?;
+        debug!("{:#?}", response);
+
+        Ok(response)
+    }
+
+    /// Downloads the package's signature and verifies it against the trusted keys.
+    /// Packages without a signature are only allowed if signatures aren't required.
+    fn check_signature(
+        &self,
+        package_id: PackageId,
+        download_url: &str,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        if self.trusted_keys.is_empty() {
+            return Ok(());
+        }
+
+        let url = utils::signature_url(&self.signature_url, download_url, package_id);
+        debug!("Downloading signature: {}", url);
+
+        let response = self.get(&url)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            if self.require_signatures {
+                anyhow::bail!(
+                    "No signature found for {}-{} at {}",
+                    package_id.name(),
+                    package_id.version(),
+                    url
+                );
+            }
+
+            println!(
+                "{}-{} has no signature, so it wasn't verified",
+                package_id.name(),
+                package_id.version()
+            );
+            return Ok(());
+        }
+
+        let signature = response.error_for_status()?.text()?;
+        self.trusted_keys.verify(data, &signature).map_err(|e| {
+            e.context(format!(
+                "Invalid signature for {}-{}",
+                package_id.name(),
+                package_id.version()
+            ))
+        })?;
+        debug!("Verified signature: {}", url);
+
+        Ok(())
+    }
+
+    /// Compares the checksum of a `.crate` file with the checksums in the registry index and `Cargo.lock`
+    fn check_checksums(&mut self, package_id: PackageId, checksum: String) -> anyhow::Result<()> {
+        let index_checksum = self.index_checksum(package_id)?;
+
+        // A package that changed since it was locked is reported first, whether or not the index changed with it
+        if let Some(locked) = self.locked_checksums.get(&package_id) {
+            if *locked != checksum {
+                let error = ArtifactChanged {
+                    package_id,
+                    locked: locked.clone(),
+                    downloaded: checksum,
+                    index: index_checksum,
+                };
+                self.config.shell().error(error.details())?;
+                return Err(error.into());
+            }
+        }
+
+        if let Some(expected) = &index_checksum {
+            if *expected != checksum {
+                anyhow::bail!(
+                    "Checksum mismatch for {}-{}: the registry index has {} but the downloaded file has {}. Double check your download url and headers.",
+                    package_id.name(),
+                    package_id.version(),
+                    expected,
+                    checksum
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The checksum of the package in the registry index. Yanked versions are included.
+    fn index_checksum(&mut self, package_id: PackageId) -> anyhow::Result<Option<String>> {
+        let req = VersionReq::exact(package_id.version());
+        let summaries = utils::package_summaries_with_yanked(
+            self.config,
+            &mut self.registry,
+            &package_id.name(),
+            &req,
+        )?;
+
+        Ok(summaries
+            .iter()
+            .find(|summary| summary.package_id() == package_id)
+            .and_then(|summary| summary.checksum().map(String::from)))
+    }
+
+    /// Package cache path for the specified registry
+    fn target_dir(&self) -> Filesystem {
+        let registry_directory = utils::registry_directory(self.registry.source_id());
+        self.config.registry_cache_path().join(&registry_directory)
+    }
+
+    /// Unpacked source path for the specified registry
+    fn source_dir(&self) -> Filesystem {
+        let registry_directory = utils::registry_directory(self.registry.source_id());
+        self.config.registry_source_path().join(&registry_directory)
+    }
+
+    /// Deletes an existing cached package file and the source that Cargo unpacked from it.
+    /// Nothing is deleted if the package isn't cached.
+    pub fn delete_existing(&self, package_id: PackageId) -> anyhow::Result<()> {
+        let _package_cache_lock = self.config.acquire_package_cache_lock()?;
+
+        let name = format!("{}-{}", package_id.name(), package_id.version());
+
+        let file_path = self
+            .target_dir()
+            .join(format!("{}.crate", name))
+            .into_path_unlocked();
+        match std::fs::remove_file(&file_path) {
+            Ok(()) => debug!("Removed: {:?}", file_path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        // Cargo skips unpacking while the directory and its `.cargo-ok` marker exist,
+        // so a stale source would be used instead of the new download
+        let source_path = self.source_dir().join(&name).into_path_unlocked();
+        match std::fs::remove_dir_all(&source_path) {
+            Ok(()) => debug!("Removed: {:?}", source_path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(())
+    }
+}
+
+/// The registry is serving a package that doesn't match the checksum it had when it was added to `Cargo.lock`
+#[derive(Debug)]
+pub struct ArtifactChanged {
+    pub package_id: PackageId,
+    pub locked: String,
+    pub downloaded: String,
+    pub index: Option<String>,
+}
+
+impl ArtifactChanged {
+    /// A description of the change that's meant to be hard to miss
+    fn details(&self) -> String {
+        [
+            format!(
+                "ARTIFACT CHANGED: {}-{} doesn't match the checksum in Cargo.lock",
+                self.package_id.name(),
+                self.package_id.version()
+            ),
+            format!("  Cargo.lock:     {}", self.locked),
+            format!("  Downloaded:     {}", self.downloaded),
+            format!(
+                "  Registry index: {}",
+                self.index.as_deref().unwrap_or("none")
+            ),
+            "The registry is serving different contents for a version that was already locked.".to_string(),
+            "It may have been republished under the same version, or the registry may have been tampered with.".to_string(),
+            "Don't trust this version until you've found out why it changed.".to_string(),
+        ]
+        .join("\n")
+    }
+}
+
+impl fmt::Display for ArtifactChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Artifact changed for {}-{}: the checksum doesn't match Cargo.lock",
+            self.package_id.name(),
+            self.package_id.version()
+        )
+    }
+}
+
+impl std::error::Error for ArtifactChanged {}
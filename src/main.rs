@@ -1,6 +1,10 @@
+pub mod api;
 mod args;
 pub mod commands;
 pub mod config;
+pub mod downloader;
+pub mod manifest;
+pub mod signature;
 pub mod utils;
 
 use crate::{args::CargoSideloadArgs, config::Config};
@@ -15,7 +19,17 @@ fn main() -> anyhow::Result<()> {
     match args {
         CargoSideloadArgs::Fetch(fetch_args) => commands::fetch(fetch_args)?,
         CargoSideloadArgs::List(list_args) => commands::list(list_args)?,
+        CargoSideloadArgs::Info(info_args) => commands::info(info_args)?,
         CargoSideloadArgs::Outdated(od_args) => commands::outdated(od_args)?,
+        CargoSideloadArgs::Search(search_args) => commands::search(search_args)?,
+        CargoSideloadArgs::Diff(diff_args) => commands::diff(diff_args)?,
+        CargoSideloadArgs::Download(download_args) => commands::download(download_args)?,
+        CargoSideloadArgs::Cache(cache_args) => commands::cache(cache_args)?,
+        CargoSideloadArgs::Verify(verify_args) => commands::verify(verify_args)?,
+        CargoSideloadArgs::Registry(registry_args) => commands::registry(registry_args)?,
+        CargoSideloadArgs::Publish(publish_args) => commands::publish(publish_args)?,
+        CargoSideloadArgs::Yank(yank_args) => commands::yank(yank_args)?,
+        CargoSideloadArgs::Owner(owner_args) => commands::owner(owner_args)?,
     }
 
     Ok(())
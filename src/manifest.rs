@@ -0,0 +1,694 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use cargo::sources::CRATES_IO_INDEX;
+use semver::{Version, VersionReq};
+use toml_edit::{decorated, Document, Item, Table, Value};
+
+/// Tables that can contain dependencies, either at the top level of the manifest or under `[target.'cfg(...)']`
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The name of a single dependency from each of the `DEPENDENCY_TABLES`
+const DEPENDENCY_KINDS: &[&str] = &["dependency", "dev-dependency", "build-dependency"];
+
+/// A package that should be updated from its `current` version to a new `version`
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub name: String,
+    pub current: Version,
+    pub version: Version,
+}
+
+/// A version requirement that was changed in a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Rewrites the version requirements of the dependencies from the specified registry so they require the updated versions.
+/// Only requirements that match a package's current version are changed. Formatting and comments are kept as they are.
+/// Returns the new manifest along with the requirements that were changed.
+pub fn update_requirements(
+    manifest: &str,
+    registry_name: &str,
+    updates: &[Update],
+) -> anyhow::Result<(String, Vec<Change>)> {
+    let mut document: Document = manifest.parse()?;
+    let mut changes = Vec::new();
+
+    let root = document.as_table_mut();
+
+    for table_name in DEPENDENCY_TABLES {
+        if let Some(table) = root.get_mut(table_name).and_then(Item::as_table_mut) {
+            update_table(table, registry_name, updates, &mut changes);
+        }
+    }
+
+    if let Some(targets) = root.get_mut("target").and_then(Item::as_table_mut) {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_table_mut() {
+                for table_name in DEPENDENCY_TABLES {
+                    if let Some(table) = target.get_mut(table_name).and_then(Item::as_table_mut) {
+                        update_table(table, registry_name, updates, &mut changes);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok((document.to_string(), changes))
+}
+
+fn update_table(
+    table: &mut Table,
+    registry_name: &str,
+    updates: &[Update],
+    changes: &mut Vec<Change>,
+) {
+    for (key, item) in table.iter_mut() {
+        // Dependencies from other registries are always tables, so plain version strings can be skipped
+        let dependency = match item.as_table_like() {
+            Some(dependency) => dependency,
+            None => continue,
+        };
+
+        if dependency.get("registry").and_then(Item::as_str) != Some(registry_name) {
+            continue;
+        }
+
+        // Renamed dependencies have the actual package name in the `package` key
+        let name = dependency
+            .get("package")
+            .and_then(Item::as_str)
+            .unwrap_or(key)
+            .to_string();
+
+        let version = match version_value(item) {
+            Some(version) => version,
+            None => continue,
+        };
+
+        let current_req = match version.as_str() {
+            Some(current_req) => current_req.to_string(),
+            None => continue,
+        };
+
+        let update = match VersionReq::parse(&current_req) {
+            Ok(req) => updates
+                .iter()
+                .find(|update| update.name == name && req.matches(&update.current)),
+            Err(_) => None,
+        };
+
+        if let Some(update) = update {
+            let new_req = updated_requirement(&current_req, &update.version);
+            if new_req == current_req {
+                continue;
+            }
+
+            let prefix = version.decor().prefix().to_string();
+            let suffix = version.decor().suffix().to_string();
+            *version = decorated(Value::from(new_req.as_str()), &prefix, &suffix);
+
+            changes.push(Change {
+                name,
+                from: current_req,
+                to: new_req,
+            });
+        }
+    }
+}
+
+/// Returns the `version` value of a dependency written as either a table or an inline table
+fn version_value(item: &mut Item) -> Option<&mut Value> {
+    match item {
+        Item::Table(table) => table.get_mut("version").and_then(Item::as_value_mut),
+        Item::Value(value) => value
+            .as_inline_table_mut()
+            .and_then(|table| table.get_mut("version")),
+        _ => None,
+    }
+}
+
+/// A dependency or feature that was added, removed or changed between two versions of a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestChange {
+    DependencyAdded {
+        kind: String,
+        name: String,
+        req: String,
+    },
+    DependencyRemoved {
+        kind: String,
+        name: String,
+        req: String,
+    },
+    DependencyChanged {
+        kind: String,
+        name: String,
+        from: String,
+        to: String,
+    },
+    FeatureAdded {
+        name: String,
+        activations: Vec<String>,
+    },
+    FeatureRemoved {
+        name: String,
+    },
+    FeatureChanged {
+        name: String,
+        from: Vec<String>,
+        to: Vec<String>,
+    },
+}
+
+impl Display for ManifestChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestChange::DependencyAdded { kind, name, req } => {
+                write!(f, "+ {} {} {}", kind, name, req)
+            }
+            ManifestChange::DependencyRemoved { kind, name, req } => {
+                write!(f, "- {} {} {}", kind, name, req)
+            }
+            ManifestChange::DependencyChanged {
+                kind,
+                name,
+                from,
+                to,
+            } => write!(f, "~ {} {} {} -> {}", kind, name, from, to),
+            ManifestChange::FeatureAdded { name, activations } => {
+                write!(f, "+ feature {} = [{}]", name, activations.join(", "))
+            }
+            ManifestChange::FeatureRemoved { name } => write!(f, "- feature {}", name),
+            ManifestChange::FeatureChanged { name, from, to } => write!(
+                f,
+                "~ feature {} [{}] -> [{}]",
+                name,
+                from.join(", "),
+                to.join(", ")
+            ),
+        }
+    }
+}
+
+/// Compares the dependencies and features of two versions of a manifest
+pub fn manifest_changes(old: &str, new: &str) -> anyhow::Result<Vec<ManifestChange>> {
+    let old: toml::Value = toml::from_str(old)?;
+    let new: toml::Value = toml::from_str(new)?;
+
+    let mut changes = Vec::new();
+
+    let old_deps = dependency_requirements(&old);
+    let new_deps = dependency_requirements(&new);
+    for ((kind, name), req) in &new_deps {
+        match old_deps.get(&(kind.clone(), name.clone())) {
+            None => changes.push(ManifestChange::DependencyAdded {
+                kind: kind.clone(),
+                name: name.clone(),
+                req: req.clone(),
+            }),
+            Some(old_req) if old_req != req => changes.push(ManifestChange::DependencyChanged {
+                kind: kind.clone(),
+                name: name.clone(),
+                from: old_req.clone(),
+                to: req.clone(),
+            }),
+            Some(_) => (),
+        }
+    }
+    for ((kind, name), req) in &old_deps {
+        if !new_deps.contains_key(&(kind.clone(), name.clone())) {
+            changes.push(ManifestChange::DependencyRemoved {
+                kind: kind.clone(),
+                name: name.clone(),
+                req: req.clone(),
+            });
+        }
+    }
+
+    let old_features = features(&old);
+    let new_features = features(&new);
+    for (name, activations) in &new_features {
+        match old_features.get(name) {
+            None => changes.push(ManifestChange::FeatureAdded {
+                name: name.clone(),
+                activations: activations.clone(),
+            }),
+            Some(old_activations) if old_activations != activations => {
+                changes.push(ManifestChange::FeatureChanged {
+                    name: name.clone(),
+                    from: old_activations.clone(),
+                    to: activations.clone(),
+                })
+            }
+            Some(_) => (),
+        }
+    }
+    for name in old_features.keys() {
+        if !new_features.contains_key(name) {
+            changes.push(ManifestChange::FeatureRemoved { name: name.clone() });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Returns the version requirement of each dependency, keyed by the kind of dependency and its name.
+/// Target-specific dependencies include the target in their kind, like `dependency (cfg(windows))`.
+fn dependency_requirements(manifest: &toml::Value) -> BTreeMap<(String, String), String> {
+    let mut tables = Vec::new();
+    for (table_name, kind) in DEPENDENCY_TABLES.iter().zip(DEPENDENCY_KINDS) {
+        if let Some(table) = manifest.get(table_name).and_then(toml::Value::as_table) {
+            tables.push((kind.to_string(), table));
+        }
+    }
+
+    if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+        for (target, target_table) in targets {
+            for (table_name, kind) in DEPENDENCY_TABLES.iter().zip(DEPENDENCY_KINDS) {
+                if let Some(table) = target_table.get(table_name).and_then(toml::Value::as_table) {
+                    tables.push((format!("{} ({})", kind, target), table));
+                }
+            }
+        }
+    }
+
+    let mut requirements = BTreeMap::new();
+    for (kind, table) in tables {
+        for (name, dependency) in table {
+            let req = match dependency {
+                toml::Value::String(req) => req.as_str(),
+                dependency => dependency
+                    .get("version")
+                    .and_then(toml::Value::as_str)
+                    .unwrap_or("*"),
+            };
+            requirements.insert((kind.clone(), name.clone()), req.to_string());
+        }
+    }
+
+    requirements
+}
+
+/// Returns the features and what each one activates
+fn features(manifest: &toml::Value) -> BTreeMap<String, Vec<String>> {
+    let table = match manifest.get("features").and_then(toml::Value::as_table) {
+        Some(table) => table,
+        None => return BTreeMap::new(),
+    };
+
+    table
+        .iter()
+        .map(|(name, activations)| {
+            let activations = activations
+                .as_array()
+                .map(|activations| {
+                    activations
+                        .iter()
+                        .filter_map(toml::Value::as_str)
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            (name.clone(), activations)
+        })
+        .collect()
+}
+
+/// Creates the registry index entry for a packaged crate from its `Cargo.toml`.
+/// Dependencies from the registry at `index_url`, or from a registry that's only given by name, are stored without a registry
+/// so they're found in the same registry. Dependencies without a version, like path and git dependencies, are left out.
+pub fn index_entry(
+    manifest: &str,
+    checksum: &str,
+    index_url: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let manifest: toml::Value = toml::from_str(manifest)?;
+    let package = match manifest.get("package") {
+        Some(package) => package,
+        None => anyhow::bail!("The manifest has no [package] table"),
+    };
+    let name = match package.get("name").and_then(toml::Value::as_str) {
+        Some(name) => name,
+        None => anyhow::bail!("The manifest has no package name"),
+    };
+    let version = match package.get("version").and_then(toml::Value::as_str) {
+        Some(version) => Version::parse(version)?,
+        None => anyhow::bail!("The manifest has no package version"),
+    };
+
+    let mut tables = Vec::new();
+    for (table_name, kind) in DEPENDENCY_TABLES.iter().zip(&["normal", "dev", "build"]) {
+        if let Some(table) = manifest.get(table_name).and_then(toml::Value::as_table) {
+            tables.push((kind, None, table));
+        }
+    }
+    if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+        for (target, target_table) in targets {
+            for (table_name, kind) in DEPENDENCY_TABLES.iter().zip(&["normal", "dev", "build"]) {
+                if let Some(table) = target_table.get(table_name).and_then(toml::Value::as_table) {
+                    tables.push((kind, Some(target.as_str()), table));
+                }
+            }
+        }
+    }
+
+    let mut deps = Vec::new();
+    for (kind, target, table) in tables {
+        for (dep_name, dependency) in table {
+            let req = match dependency {
+                toml::Value::String(req) => Some(req.as_str()),
+                dependency => dependency.get("version").and_then(toml::Value::as_str),
+            };
+            let req = match req {
+                Some(req) => VersionReq::parse(req)?,
+                None => continue,
+            };
+
+            let get_bool = |key: &str| dependency.get(key).and_then(toml::Value::as_bool);
+            let registry = match dependency
+                .get("registry-index")
+                .and_then(toml::Value::as_str)
+            {
+                Some(url) if url.trim_end_matches('/') == index_url.trim_end_matches('/') => None,
+                Some(url) => Some(url),
+                None if dependency.get("registry").is_some() => None,
+                None => Some(CRATES_IO_INDEX),
+            };
+            let features: Vec<&str> = dependency
+                .get("features")
+                .and_then(toml::Value::as_array)
+                .map(|features| features.iter().filter_map(toml::Value::as_str).collect())
+                .unwrap_or_default();
+
+            deps.push(serde_json::json!({
+                "name": dep_name,
+                "req": req.to_string(),
+                "features": features,
+                "optional": get_bool("optional").unwrap_or(false),
+                "default_features": get_bool("default-features")
+                    .or_else(|| get_bool("default_features"))
+                    .unwrap_or(true),
+                "target": target,
+                "kind": kind,
+                "registry": registry,
+                "package": dependency.get("package").and_then(toml::Value::as_str),
+            }));
+        }
+    }
+
+    let mut entry = serde_json::json!({
+        "name": name,
+        "vers": version.to_string(),
+        "deps": deps,
+        "cksum": checksum,
+        "features": features(&manifest),
+        "yanked": false,
+        "links": package.get("links").and_then(toml::Value::as_str),
+    });
+    if let Some(rust_version) = package.get("rust-version").and_then(toml::Value::as_str) {
+        entry["rust_version"] = rust_version.into();
+    }
+
+    Ok(entry)
+}
+
+/// Returns the `rust-version` of the package, or the one set in `[workspace.package]` if the package doesn't have one
+pub fn rust_version(manifest: &str) -> anyhow::Result<Option<Version>> {
+    let manifest: toml::Value = toml::from_str(manifest)?;
+
+    let package_rust_version = manifest
+        .get("package")
+        .and_then(|package| package.get("rust-version"))
+        .and_then(toml::Value::as_str);
+    let workspace_rust_version = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("package"))
+        .and_then(|package| package.get("rust-version"))
+        .and_then(toml::Value::as_str);
+
+    package_rust_version
+        .or(workspace_rust_version)
+        .map(parse_rust_version)
+        .transpose()
+}
+
+/// Parses a Rust version like `1.56` or `1.56.1`. Missing parts of the version are set to zero.
+pub fn parse_rust_version(rust_version: &str) -> anyhow::Result<Version> {
+    let rust_version = rust_version.trim();
+    let parts: Vec<&str> = rust_version.split('.').collect();
+    if parts.len() > 3 || parts.iter().any(|part| part.parse::<u64>().is_err()) {
+        anyhow::bail!("Invalid Rust version `{}`", rust_version);
+    }
+
+    let mut parts = parts;
+    parts.resize(3, "0");
+    Ok(Version::parse(&parts.join("."))?)
+}
+
+/// Creates a requirement for the new version that keeps the `=`, `~` or `^` operator of the current requirement.
+/// Requirements with multiple comparisons are replaced by a default (caret) requirement.
+fn updated_requirement(current_req: &str, version: &Version) -> String {
+    let current_req = current_req.trim();
+
+    let operator = match current_req.chars().next() {
+        Some(operator @ '=') | Some(operator @ '~') | Some(operator @ '^')
+            if !current_req.contains(',') =>
+        {
+            operator.to_string()
+        }
+        _ => String::new(),
+    };
+
+    format!("{}{}", operator, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(name: &str, current: &str, version: &str) -> Update {
+        Update {
+            name: name.to_string(),
+            current: Version::parse(current).unwrap(),
+            version: Version::parse(version).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_update_requirements() {
+        let manifest = r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+# The library we care about
+my_lib = { version = "0.1", registry = "test_registry" } # keep me
+renamed = { package = "other_lib", version = "=1.0.0", registry = "test_registry" }
+public_lib = "0.1"
+other_registry_lib = { version = "0.1", registry = "other_registry" }
+
+[dev-dependencies.test_lib]
+version = "~0.3.1"
+registry = "test_registry"
+
+[target.'cfg(windows)'.dependencies]
+win_lib = { version = "0.1", registry = "test_registry" }
+"#;
+
+        let updates = vec![
+            update("my_lib", "0.1.0", "0.1.1"),
+            update("other_lib", "1.0.0", "2.0.0"),
+            update("public_lib", "0.1.0", "0.2.0"),
+            update("other_registry_lib", "0.1.0", "0.2.0"),
+            update("test_lib", "0.3.1", "0.3.4"),
+            update("win_lib", "0.1.0", "0.2.0"),
+        ];
+
+        let (updated, changes) = update_requirements(manifest, "test_registry", &updates).unwrap();
+
+        let expected = r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+# The library we care about
+my_lib = { version = "0.1.1", registry = "test_registry" } # keep me
+renamed = { package = "other_lib", version = "=2.0.0", registry = "test_registry" }
+public_lib = "0.1"
+other_registry_lib = { version = "0.1", registry = "other_registry" }
+
+[dev-dependencies.test_lib]
+version = "~0.3.4"
+registry = "test_registry"
+
+[target.'cfg(windows)'.dependencies]
+win_lib = { version = "0.2.0", registry = "test_registry" }
+"#;
+
+        assert_eq!(updated, expected);
+        assert_eq!(changes.len(), 4);
+        assert_eq!(
+            changes[1],
+            Change {
+                name: "other_lib".to_string(),
+                from: "=1.0.0".to_string(),
+                to: "=2.0.0".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_update_requirements_skips_other_versions() {
+        let manifest = r#"
+[dependencies]
+my_lib = { version = "0.2", registry = "test_registry" }
+"#;
+
+        let updates = vec![update("my_lib", "0.1.0", "0.1.1")];
+        let (updated, changes) = update_requirements(manifest, "test_registry", &updates).unwrap();
+
+        assert_eq!(updated, manifest);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_manifest_changes() {
+        let old = r#"
+[dependencies]
+my_lib = { version = "0.1", registry = "test_registry" }
+old_lib = "1.0"
+
+[target.'cfg(windows)'.dependencies]
+win_lib = "0.1"
+
+[features]
+default = ["std"]
+std = []
+legacy = []
+"#;
+        let new = r#"
+[dependencies]
+my_lib = { version = "0.2", registry = "test_registry" }
+
+[dev-dependencies]
+test_lib = "0.3"
+
+[target.'cfg(windows)'.dependencies]
+win_lib = "0.1"
+
+[features]
+default = ["std", "extra"]
+std = []
+extra = ["my_lib/extra"]
+"#;
+
+        let changes: Vec<String> = manifest_changes(old, new)
+            .unwrap()
+            .iter()
+            .map(ManifestChange::to_string)
+            .collect();
+
+        assert_eq!(
+            changes,
+            vec![
+                "~ dependency my_lib 0.1 -> 0.2",
+                "+ dev-dependency test_lib 0.3",
+                "- dependency old_lib 1.0",
+                "~ feature default [std] -> [std, extra]",
+                "+ feature extra = [my_lib/extra]",
+                "- feature legacy",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rust_version() {
+        let package = r#"
+[package]
+name = "app"
+rust-version = "1.56"
+"#;
+        let workspace = r#"
+[workspace.package]
+rust-version = "1.60.1"
+"#;
+
+        assert_eq!(
+            rust_version(package).unwrap(),
+            Some(Version::parse("1.56.0").unwrap())
+        );
+        assert_eq!(
+            rust_version(workspace).unwrap(),
+            Some(Version::parse("1.60.1").unwrap())
+        );
+        assert_eq!(rust_version("[package]\nname = \"app\"").unwrap(), None);
+        assert!(parse_rust_version("1.56-beta").is_err());
+        assert!(parse_rust_version("1.2.3.4").is_err());
+    }
+
+    #[test]
+    fn test_updated_requirement() {
+        let version = Version::parse("1.2.3").unwrap();
+
+        assert_eq!(updated_requirement("1.0", &version), "1.2.3");
+        assert_eq!(updated_requirement("^1.0", &version), "^1.2.3");
+        assert_eq!(updated_requirement("=1.0.0", &version), "=1.2.3");
+        assert_eq!(updated_requirement("~1.0", &version), "~1.2.3");
+        assert_eq!(updated_requirement(">=1.0, <2.0", &version), "1.2.3");
+    }
+
+    #[test]
+    fn test_index_entry() {
+        let manifest = r#"
+[package]
+name = "my_lib"
+version = "0.3.0"
+links = "foo"
+rust-version = "1.56"
+
+[dependencies]
+core_lib = { version = "0.1", registry-index = "file:///tmp/registry/index" }
+other = { version = "1.0", package = "other_lib", registry-index = "https://example.com/index", optional = true }
+serde = { version = "1.0", default-features = false, features = ["derive"] }
+local = { path = "../local" }
+
+[target.'cfg(windows)'.dev-dependencies]
+win_lib = { version = "=0.1.0", registry = "test_registry" }
+
+[features]
+default = ["other"]
+"#;
+
+        let entry = index_entry(manifest, "abcd", "file:///tmp/registry/index/").unwrap();
+        assert_eq!(
+            entry,
+            serde_json::json!({
+                "name": "my_lib",
+                "vers": "0.3.0",
+                "deps": [
+                    {"name": "core_lib", "req": "^0.1", "features": [], "optional": false, "default_features": true,
+                        "target": null, "kind": "normal", "registry": null, "package": null},
+                    {"name": "other", "req": "^1.0", "features": [], "optional": true, "default_features": true,
+                        "target": null, "kind": "normal", "registry": "https://example.com/index", "package": "other_lib"},
+                    {"name": "serde", "req": "^1.0", "features": ["derive"], "optional": false, "default_features": false,
+                        "target": null, "kind": "normal", "registry": CRATES_IO_INDEX, "package": null},
+                    {"name": "win_lib", "req": "=0.1.0", "features": [], "optional": false, "default_features": true,
+                        "target": "cfg(windows)", "kind": "dev", "registry": null, "package": null},
+                ],
+                "cksum": "abcd",
+                "features": {"default": ["other"]},
+                "yanked": false,
+                "links": "foo",
+                "rust_version": "1.56",
+            })
+        );
+
+        assert!(index_entry("[package]\nname = \"my_lib\"", "abcd", "").is_err());
+    }
+}
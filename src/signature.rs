@@ -0,0 +1,89 @@
+use minisign_verify::{PublicKey, Signature};
+
+/// The minisign public keys that are trusted to sign a registry's crates
+pub struct TrustedKeys {
+    keys: Vec<PublicKey>,
+}
+
+impl TrustedKeys {
+    /// Parses base64 public keys, the format printed by `minisign -G` and stored on the second line of `minisign.pub`
+    pub fn new(keys: &[String]) -> anyhow::Result<Self> {
+        let keys = keys
+            .iter()
+            .map(|key| {
+                PublicKey::from_base64(key.trim())
+                    .map_err(|e| anyhow::anyhow!("Invalid trusted key `{}`: {}", key, e))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Checks a minisign signature of the data against each trusted key.
+    /// The signature has to be made by one of the trusted keys.
+    pub fn verify(&self, data: &[u8], signature: &str) -> anyhow::Result<()> {
+        let signature = Signature::decode(signature)
+            .map_err(|e| anyhow::anyhow!("Invalid signature file: {}", e))?;
+
+        let mut last_error = None;
+        for key in &self.keys {
+            // Legacy signatures, which aren't prehashed, are refused like `minisign -V` does by default
+            match key.verify(data, &signature, false) {
+                Ok(()) => return Ok(()),
+                Err(minisign_verify::Error::UnexpectedKeyId) => continue,
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match last_error {
+            Some(e) => anyhow::bail!("{}", e),
+            None => anyhow::bail!("The signature wasn't made by any of the trusted keys"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "RWRGrqICE7U6FNjgZrrfRo+CvECMCvJkNaKZLSroGHBNMSGcxn8WgOSq";
+    const OTHER_KEY: &str = "RWR5GHdW16dDdwVc9lMB8ZE6Z3I0bB82jcE6M56n8zsIK5IAjYsCjoPO";
+    const SIGNATURE: &str = "untrusted comment: signature
+RURGrqICE7U6FJUUzhHj1auS3W8qQMo6WYw66bBzZRsqbjlFb0rewoI4eieT8f4xAafMObkMMhoHikv2zjQCvgUsEHD2+5iRsQc=
+trusted comment: timestamp:0\tfile:hello.txt
+zJyWtJaw0Y7cQQmIx/DJOyyP0GG5K+FSdXPacA+XPFauxezMUkKvidUS0Qn3KY0xYFftyY0Degv76Q1JDAcqDw==
+";
+    const LEGACY_KEY: &str = "RWR7nPDYylPRnTtBkC9MBiMdRLM+GjAsyVART92SzL8MpAGOAS328wDO";
+    const LEGACY_SIGNATURE: &str = "untrusted comment: signature
+RWR7nPDYylPRnY9nC7YogyC07SPdI0w/UZbDhAwti6/9WUiS2V7jhVhNv8I49+NAMTrj8ZdYJ+fVubOga3ZTWDIzLhS5lrIetQA=
+trusted comment: timestamp:0\tfile:hello.txt
+3LDlkKhE9Rc7HqMmzBpfLSps2o9aCXH1iKAA1LIX3kKWqMEFL0cwi/h+nbBE13jknvokB9tKSuZPC43k1YRVBg==
+";
+
+    #[test]
+    fn test_trusted_keys_verify() {
+        let keys = TrustedKeys::new(&[OTHER_KEY.to_string(), KEY.to_string()]).unwrap();
+        assert!(keys.verify(b"hello", SIGNATURE).is_ok());
+        assert!(keys.verify(b"hello!", SIGNATURE).is_err());
+        assert!(keys.verify(b"hello", "not a signature").is_err());
+
+        let other_keys = TrustedKeys::new(&[OTHER_KEY.to_string()]).unwrap();
+        assert!(other_keys.verify(b"hello", SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn test_trusted_keys_legacy_signature() {
+        let keys = TrustedKeys::new(&[LEGACY_KEY.to_string()]).unwrap();
+        assert!(keys.verify(b"hello", LEGACY_SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn test_trusted_keys_invalid() {
+        assert!(TrustedKeys::new(&["abcd".to_string()]).is_err());
+        assert!(TrustedKeys::new(&[]).unwrap().is_empty());
+    }
+}
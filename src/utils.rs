@@ -1,32 +1,43 @@
-use std::{collections::HashSet, fs::canonicalize, path::Path};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::{self, canonicalize},
+    io::Read,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
 use cargo::{
     core::{
-        resolver::EncodableResolve, Dependency, PackageId, Resolve, Source, SourceId, Summary,
+        compiler::{CompileKind, CompileTarget, RustcTargetData},
+        dependency::DepKind,
+        registry::PackageRegistry,
+        resolver::{EncodableResolve, ResolveOpts},
+        Dependency, GitReference, PackageId, PackageIdSpec, Resolve, Source, SourceId, Summary,
         Workspace,
     },
-    sources::{RegistrySource, CRATES_IO_INDEX, CRATES_IO_REGISTRY},
+    sources::{registry::RegistryConfig, RegistrySource, CRATES_IO_INDEX, CRATES_IO_REGISTRY},
     Config as CargoConfig,
 };
+use log::debug;
+use semver::{Version, VersionReq};
 use url::Url;
 
-use crate::args::CargoSideloadCommonArgs;
+use crate::{
+    args::{CargoSideloadCommonArgs, CargoSideloadResolveArgs, CrateSpec},
+    manifest,
+};
 
 pub fn create_registry<'cfg>(
     config: &'cfg CargoConfig,
     registry_name: &str,
 ) -> anyhow::Result<RegistrySource<'cfg>> {
-    let index_url = registry_index_url(&config, registry_name)?;
+    let index_url = registry_index_url(config, registry_name)?;
     let url = Url::parse(&index_url)?;
 
     let source_id = SourceId::for_registry(&url)?;
     let yanked_whitelist = HashSet::new();
 
-    Ok(RegistrySource::remote(
-        source_id,
-        &yanked_whitelist,
-        &config,
-    ))
+    Ok(RegistrySource::remote(source_id, &yanked_whitelist, config))
 }
 
 /// Updates the local copy of a registry index
@@ -52,26 +63,672 @@ pub fn package_summaries<S: Source>(
     Ok(summaries)
 }
 
-pub fn latest_version(summaries: &[Summary]) -> Option<&Summary> {
-    summaries.iter().max_by_key(|summary| summary.version())
+/// Returns the package's summaries, including the yanked versions that match the version requirement
+pub fn package_summaries_with_yanked<S: Source>(
+    config: &CargoConfig,
+    source: &mut S,
+    package: &str,
+    req: &VersionReq,
+) -> anyhow::Result<Vec<Summary>> {
+    let yanked: Vec<PackageId> = yanked_versions(config, source.source_id(), package)?
+        .iter()
+        .filter(|version| req.matches(version))
+        .map(|version| PackageId::new(package, version, source.source_id()))
+        .collect::<Result<_, _>>()?;
+
+    // Cargo only returns yanked versions that have been added to the source's whitelist
+    source.add_to_yanked_whitelist(&yanked);
+    package_summaries(config, source, package)
+}
+
+/// Returns the latest version. Pre-releases are only included if `include_prerelease` is set.
+pub fn latest_version(summaries: &[Summary], include_prerelease: bool) -> Option<&Summary> {
+    summaries
+        .iter()
+        .filter(|summary| include_prerelease || !summary.version().is_prerelease())
+        .max_by_key(|summary| summary.version())
+}
+
+/// Returns the latest version that a package at the `current` version could be updated to.
+/// Pre-releases are only included if `include_prerelease` is set,
+/// or if they are on the same pre-release train as `current`.
+pub fn latest_update<'a>(
+    summaries: &'a [Summary],
+    current: &Version,
+    include_prerelease: bool,
+) -> Option<&'a Summary> {
+    summaries
+        .iter()
+        .filter(|summary| is_update_candidate(summary.version(), current, include_prerelease))
+        .max_by_key(|summary| summary.version())
+}
+
+/// Pre-releases of the same `major.minor.patch` version as a pre-release `current` version are on its
+/// pre-release train. For example, `1.0.0-beta.5` is on the same train as `1.0.0-beta.2`.
+fn is_update_candidate(version: &Version, current: &Version, include_prerelease: bool) -> bool {
+    if include_prerelease || !version.is_prerelease() {
+        return true;
+    }
+
+    current.is_prerelease()
+        && version.major == current.major
+        && version.minor == current.minor
+        && version.patch == current.patch
+}
+
+/// Returns the latest version that satisfies the version requirement
+pub fn matching_version<'a>(summaries: &'a [Summary], req: &VersionReq) -> Option<&'a Summary> {
+    // An empty requirement matches pre-releases, but it's used to mean the latest release
+    if *req == VersionReq::any() {
+        return latest_version(summaries, false);
+    }
+
+    summaries
+        .iter()
+        .filter(|summary| req.matches(summary.version()))
+        .max_by_key(|summary| summary.version())
+}
+
+/// Returns the latest version that satisfies every one of the version requirements
+pub fn compatible_version<'a>(
+    summaries: &'a [Summary],
+    reqs: &[VersionReq],
+) -> Option<&'a Summary> {
+    summaries
+        .iter()
+        .filter(|summary| reqs.iter().all(|req| req.matches(summary.version())))
+        .max_by_key(|summary| summary.version())
+}
+
+/// Returns the version closest to `current` that satisfies every one of the version requirements.
+/// Newer versions are preferred, so an older version is only returned if there is no newer one.
+pub fn nearest_version<'a>(
+    summaries: &'a [Summary],
+    reqs: &[VersionReq],
+    current: &Version,
+) -> Option<&'a Summary> {
+    let (newer, older): (Vec<&Summary>, Vec<&Summary>) = summaries
+        .iter()
+        .filter(|summary| summary.version() != current)
+        .filter(|summary| reqs.iter().all(|req| req.matches(summary.version())))
+        .partition(|summary| summary.version() > current);
+
+    newer
+        .into_iter()
+        .min_by_key(|summary| summary.version())
+        .or_else(|| older.into_iter().max_by_key(|summary| summary.version()))
+}
+
+/// The `rust-version` of each version of a package that has one
+pub type RustVersions = HashMap<Version, Version>;
+
+/// Returns the `rust-version` of each version of the package. The version is read from the registry index when it's there,
+/// otherwise from the manifest of the package if it's been unpacked in the local cache.
+/// A manifest that can't be read is skipped, so one broken crate in the cache doesn't stop the command.
+pub fn rust_versions(
+    config: &CargoConfig,
+    source_id: SourceId,
+    summaries: &[Summary],
+) -> RustVersions {
+    let mut rust_versions = index_rust_versions(config, source_id, summaries).unwrap_or_else(|e| {
+        debug!("Unable to read rust-version from the index: {}", e);
+        RustVersions::new()
+    });
+
+    let source_path = config
+        .registry_source_path()
+        .join(registry_directory(source_id))
+        .into_path_unlocked();
+
+    for summary in summaries {
+        if rust_versions.contains_key(summary.version()) {
+            continue;
+        }
+
+        let manifest_path = source_path
+            .join(format!("{}-{}", summary.name(), summary.version()))
+            .join("Cargo.toml");
+        let manifest = match std::fs::read_to_string(&manifest_path) {
+            Ok(manifest) => manifest,
+            Err(_) => continue,
+        };
+        match manifest::rust_version(&manifest) {
+            Ok(Some(rust_version)) => {
+                rust_versions.insert(summary.version().clone(), rust_version);
+            }
+            Ok(None) => (),
+            Err(e) => debug!(
+                "Unable to read rust-version from {}: {}",
+                manifest_path.display(),
+                e
+            ),
+        }
+    }
+
+    rust_versions
+}
+
+/// Reads the `rust_version` field of the package's index entries
+fn index_rust_versions(
+    config: &CargoConfig,
+    source_id: SourceId,
+    summaries: &[Summary],
+) -> anyhow::Result<RustVersions> {
+    let name = match summaries.first() {
+        Some(summary) => summary.name(),
+        None => return Ok(RustVersions::new()),
+    };
+
+    let mut rust_versions = RustVersions::new();
+    for entry in index_entries(config, source_id, &name)? {
+        let version = entry.get("vers").and_then(serde_json::Value::as_str);
+        let rust_version = entry
+            .get("rust_version")
+            .and_then(serde_json::Value::as_str);
+        if let (Some(version), Some(rust_version)) = (version, rust_version) {
+            rust_versions.insert(
+                Version::parse(version)?,
+                manifest::parse_rust_version(rust_version)?,
+            );
+        }
+    }
+
+    Ok(rust_versions)
+}
+
+/// Returns the versions of the package that have been yanked
+pub fn yanked_versions(
+    config: &CargoConfig,
+    source_id: SourceId,
+    name: &str,
+) -> anyhow::Result<Vec<Version>> {
+    let mut versions = Vec::new();
+    for entry in index_entries(config, source_id, name)? {
+        let yanked = entry.get("yanked").and_then(serde_json::Value::as_bool);
+        let version = entry.get("vers").and_then(serde_json::Value::as_str);
+        if let (Some(true), Some(version)) = (yanked, version) {
+            versions.push(Version::parse(version)?);
+        }
+    }
+
+    Ok(versions)
+}
+
+/// Reads the package's raw entries from the local copy of the git index, including fields that Cargo doesn't know about
+pub fn index_entries(
+    config: &CargoConfig,
+    source_id: SourceId,
+    name: &str,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    LocalIndex::open(config, source_id)?.entries(name)
+}
+
+/// The path of a package's file in the registry index, which uses the same directory layout as Cargo
+pub fn index_file_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    format!("{}/{}", index_prefix(&name), name)
+}
+
+/// The directories a package's file is in within the registry index, keeping the case of the name
+fn index_prefix(name: &str) -> String {
+    match name.len() {
+        1 => String::from("1"),
+        2 => String::from("2"),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[0..2], &name[2..4]),
+    }
+}
+
+/// The local copy of a registry's git index, as of the last update
+pub struct LocalIndex {
+    repo: git2::Repository,
+    tree_id: git2::Oid,
+}
+
+impl LocalIndex {
+    pub fn open(config: &CargoConfig, source_id: SourceId) -> anyhow::Result<Self> {
+        let index_path = config
+            .registry_index_path()
+            .join(registry_directory(source_id))
+            .into_path_unlocked();
+        Self::new(git2::Repository::open(index_path)?)
+    }
+
+    fn new(repo: git2::Repository) -> anyhow::Result<Self> {
+        let head = GitReference::DefaultBranch.resolve(&repo, None)?;
+        let tree_id = repo.find_commit(head)?.tree_id();
+        Ok(Self { repo, tree_id })
+    }
+
+    /// Returns the name of every package in the index.
+    /// Names are lowercase because they come from the index's file names.
+    pub fn package_names(&self) -> anyhow::Result<Vec<String>> {
+        let tree = self.repo.find_tree(self.tree_id)?;
+
+        let mut names = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |directory, entry| {
+            let name = match entry.name() {
+                Some(name) => name,
+                None => return git2::TreeWalkResult::Ok,
+            };
+
+            // Hidden directories like `.github` aren't part of the index
+            if entry.kind() == Some(git2::ObjectType::Tree) && name.starts_with('.') {
+                return git2::TreeWalkResult::Skip;
+            }
+
+            // Only files where Cargo would look for a package with that name are packages,
+            // which leaves out files like `config.json`
+            if entry.kind() == Some(git2::ObjectType::Blob)
+                && index_file_path(name) == format!("{}{}", directory, name)
+            {
+                names.push(name.to_string());
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// Reads the package's raw entries, including fields that Cargo doesn't know about
+    pub fn entries(&self, name: &str) -> anyhow::Result<Vec<serde_json::Value>> {
+        let tree = self.repo.find_tree(self.tree_id)?;
+
+        let entry = match tree.get_path(Path::new(&index_file_path(name))) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let blob = self.repo.find_blob(entry.id())?;
+
+        Ok(blob
+            .content()
+            .split(|&b| b == b'\n')
+            .filter_map(|line| serde_json::from_slice(line).ok())
+            .collect())
+    }
+}
+
+/// Returns true if the version of the package can be built with the Rust version.
+/// Versions without a `rust-version` are assumed to be supported.
+pub fn supports_rust_version(
+    rust_versions: &RustVersions,
+    version: &Version,
+    rust_version: &Version,
+) -> bool {
+    rust_versions
+        .get(version)
+        .map_or(true, |required| required <= rust_version)
+}
+
+/// Returns the lowest `rust-version` of the workspace members. Members without a `rust-version`
+/// use the one from `[workspace.package]` in the root manifest.
+pub fn workspace_rust_version<'cfg>(
+    workspace: &Workspace<'cfg>,
+    members: &[PackageId],
+) -> anyhow::Result<Option<Version>> {
+    let root_manifest = std::fs::read_to_string(workspace.root_manifest())?;
+    let root_rust_version = manifest::rust_version(&root_manifest)?;
+
+    let mut lowest: Option<Version> = None;
+    for member in workspace.members() {
+        if !members.contains(&member.package_id()) {
+            continue;
+        }
+
+        let manifest = std::fs::read_to_string(member.manifest_path())?;
+        let rust_version = manifest::rust_version(&manifest)?.or_else(|| root_rust_version.clone());
+        if let Some(rust_version) = rust_version {
+            if lowest
+                .as_ref()
+                .map_or(true, |lowest| rust_version < *lowest)
+            {
+                lowest = Some(rust_version);
+            }
+        }
+    }
+
+    Ok(lowest)
+}
+
+/// Returns the version requirements that the workspace members and the packages from the registry
+/// place on the specified package.
+/// Requirements from packages in other registries aren't included.
+pub fn version_requirements<'cfg, S: Source>(
+    config: &CargoConfig,
+    source: &mut S,
+    workspace: &Workspace<'cfg>,
+    resolve: &Resolve,
+    package_id: PackageId,
+) -> anyhow::Result<Vec<VersionReq>> {
+    let mut reqs = Vec::new();
+
+    for parent_id in resolve.iter() {
+        if !resolve
+            .deps(parent_id)
+            .any(|(dep_id, _)| dep_id == package_id)
+        {
+            continue;
+        }
+
+        // The dependencies in a resolve are locked to exact versions,
+        // so the original requirements have to come from the manifest or the index
+        let dependencies = match workspace
+            .members()
+            .find(|member| member.package_id() == parent_id)
+        {
+            Some(member) => member.dependencies().to_vec(),
+            None if parent_id.source_id().url() == source.source_id().url() => {
+                package_summaries(config, source, &parent_id.name())?
+                    .into_iter()
+                    .find(|summary| summary.version() == parent_id.version())
+                    .map(|summary| summary.dependencies().to_vec())
+                    .unwrap_or_default()
+            }
+            None => continue,
+        };
+
+        reqs.extend(
+            dependencies
+                .iter()
+                .filter(|dep| {
+                    dep.package_name() == package_id.name()
+                        && dep.version_req().matches(package_id.version())
+                })
+                .map(|dep| dep.version_req().clone()),
+        );
+    }
+
+    Ok(reqs)
+}
+
+/// Resolves the latest matching version of each of the specified crates against the registry
+pub fn registry_packages<S: Source>(
+    config: &CargoConfig,
+    source: &mut S,
+    crates: &[CrateSpec],
+) -> anyhow::Result<Vec<PackageId>> {
+    let mut packages = Vec::new();
+
+    for spec in crates {
+        let summaries = package_summaries(config, source, &spec.name)?;
+        match matching_version(&summaries, &spec.req) {
+            Some(summary) => packages.push(summary.package_id()),
+            None => anyhow::bail!("No version of {} matches {}", spec.name, spec.req),
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Creates a workspace whose only package depends on the specified crates.
+/// The manifest is written to `dir`, so it must not be removed until the workspace is no longer needed.
+///
+/// Features in the format `[crate-name]/[feature]` are only activated for that crate,
+/// all other features are activated for every crate.
+pub fn crates_workspace<'cfg>(
+    config: &'cfg CargoConfig,
+    registry_name: &str,
+    crates: &[CrateSpec],
+    features: &[String],
+    uses_default_features: bool,
+    dir: &Path,
+) -> anyhow::Result<Workspace<'cfg>> {
+    let mut dependencies = toml::value::Table::new();
+
+    for (index, spec) in crates.iter().enumerate() {
+        let crate_features: Vec<toml::Value> = features
+            .iter()
+            .filter_map(|feature| {
+                let mut parts = feature.splitn(2, '/');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(feature)) if name == spec.name => Some(feature),
+                    (_, Some(_)) => None,
+                    _ => Some(feature.as_str()),
+                }
+            })
+            .map(toml::Value::from)
+            .collect();
+
+        let mut dependency = toml::value::Table::new();
+        dependency.insert("package".into(), spec.name.clone().into());
+        dependency.insert("version".into(), spec.req.to_string().into());
+        dependency.insert("registry".into(), registry_name.into());
+        dependency.insert("default-features".into(), uses_default_features.into());
+        dependency.insert("features".into(), crate_features.into());
+
+        // Dependencies are renamed so that multiple versions of the same crate can be requested
+        dependencies.insert(format!("dep-{}", index), dependency.into());
+    }
+
+    let mut package = toml::value::Table::new();
+    package.insert("name".into(), "cargo-sideload-crates".into());
+    package.insert("version".into(), "0.0.0".into());
+
+    let mut lib = toml::value::Table::new();
+    lib.insert("path".into(), "lib.rs".into());
+
+    let mut manifest = toml::value::Table::new();
+    manifest.insert("package".into(), package.into());
+    manifest.insert("lib".into(), lib.into());
+    // An empty workspace table keeps Cargo from looking for a workspace in the parent directories
+    manifest.insert("workspace".into(), toml::value::Table::new().into());
+    manifest.insert("dependencies".into(), dependencies.into());
+
+    let manifest_path = dir.join("Cargo.toml");
+    std::fs::write(&manifest_path, toml::to_string(&manifest)?)?;
+    std::fs::write(dir.join("lib.rs"), "")?;
+
+    Workspace::new(&manifest_path, config)
+}
+
+/// Resolves the dependency graph of the workspace members through the registry indexes without writing a lockfile.
+/// A previous resolve, usually from `Cargo.lock`, can be used as a guide.
+/// Returns the packages from the specified registry that are needed to build the members
+/// for the target, or for the host if no target is given.
+pub fn resolved_packages<'cfg>(
+    config: &CargoConfig,
+    registry_name: &str,
+    workspace: &Workspace<'cfg>,
+    members: &[PackageId],
+    previous: Option<&Resolve>,
+    opts: &ResolveOpts,
+    target: Option<&str>,
+) -> anyhow::Result<Vec<PackageId>> {
+    let mut package_registry = PackageRegistry::new(workspace.config())?;
+    let specs: Vec<PackageIdSpec> = members
+        .iter()
+        .map(|member| PackageIdSpec::from_package_id(*member))
+        .collect();
+    let resolve = cargo::ops::resolve_with_previous(
+        &mut package_registry,
+        workspace,
+        opts,
+        previous,
+        None,
+        &specs,
+        true,
+    )?;
+
+    // Without a target, the dependencies for every platform are included, the same as when nothing is narrowed
+    let target_kind = match target {
+        Some(target) => CompileKind::Target(CompileTarget::new(target)?),
+        None => CompileKind::Host,
+    };
+    let target_data = match target {
+        Some(_) => Some(RustcTargetData::new(workspace, &[target_kind])?),
+        None => None,
+    };
+
+    let registry_index_url = registry_index_url(config, registry_name)?;
+
+    let mut queue: Vec<(PackageId, CompileKind)> = members
+        .iter()
+        .map(|member| (*member, target_kind))
+        .collect();
+    let mut visited = HashSet::new();
+    let mut packages = Vec::new();
+
+    while let Some((package_id, kind)) = queue.pop() {
+        if !visited.insert((package_id, kind)) {
+            continue;
+        }
+
+        let url = package_id.source_id().url().to_string();
+        if url == registry_index_url && !packages.contains(&package_id) {
+            packages.push(package_id);
+        }
+
+        for (dep_id, deps) in resolve.deps(package_id) {
+            for dep in deps {
+                // Build dependencies and everything they depend on are built for the host
+                let dep_kind = if dep.is_build() {
+                    CompileKind::Host
+                } else {
+                    kind
+                };
+
+                if dep.kind() == DepKind::Development && !opts.dev_deps {
+                    continue;
+                }
+
+                let is_activated = match &target_data {
+                    Some(target_data) => target_data.dep_platform_activated(dep, dep_kind),
+                    None => true,
+                };
+                if is_activated {
+                    queue.push((dep_id, dep_kind));
+                }
+            }
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Converts the feature arguments into options for Cargo's resolver
+pub fn resolve_opts(args: &CargoSideloadResolveArgs) -> ResolveOpts {
+    ResolveOpts::new(
+        !args.no_dev_deps,
+        &args.features(),
+        false,
+        !args.no_default_features,
+    )
+}
+
+/// Applies the lockfile arguments to Cargo's config
+pub fn configure(config: &mut CargoConfig, args: &CargoSideloadCommonArgs) -> anyhow::Result<()> {
+    config.configure(
+        0,
+        false,
+        None,
+        args.frozen,
+        args.locked,
+        args.frozen,
+        &None,
+        &[],
+        &[],
+    )
 }
 
 pub fn workspace_packages<'cfg>(
     config: &CargoConfig,
     args: &CargoSideloadCommonArgs,
+    resolve_args: &CargoSideloadResolveArgs,
     workspace: &Workspace<'cfg>,
 ) -> anyhow::Result<Vec<PackageId>> {
-    cargo::ops::generate_lockfile(workspace)?;
+    let lock_file = workspace_resolve(config, args, workspace)?;
+    registry_packages_in_resolve(config, args, resolve_args, workspace, &lock_file)
+}
+
+/// Resolves the workspace, reading and writing `Cargo.lock` according to the lockfile arguments
+pub fn workspace_resolve<'cfg>(
+    config: &CargoConfig,
+    args: &CargoSideloadCommonArgs,
+    workspace: &Workspace<'cfg>,
+) -> anyhow::Result<Resolve> {
+    let lock_file = if args.no_write_lockfile {
+        // Use the existing lockfile as a guide, but don't write the result
+        let previous = cargo::ops::load_pkg_lockfile(workspace)?;
+        let mut package_registry = PackageRegistry::new(config)?;
+        cargo::ops::resolve_with_previous(
+            &mut package_registry,
+            workspace,
+            &ResolveOpts::everything(),
+            previous.as_ref(),
+            None,
+            &[],
+            true,
+        )?
+    } else if args.locked || args.frozen {
+        // Cargo returns an error instead of writing the lockfile if it needs to be updated
+        let (_, resolve) = cargo::ops::resolve_ws(workspace)?;
+        resolve
+    } else {
+        cargo::ops::generate_lockfile(workspace)?;
 
-    let lock_file_path = args.path.join("Cargo.lock");
-    let lock_file_path = canonicalize(lock_file_path)?;
-    let lock_file = parse_lockfile(&lock_file_path, &workspace)?;
+        // The lockfile is always at the root of the workspace
+        let lock_file_path = workspace.root().join("Cargo.lock");
+        let lock_file_path = canonicalize(lock_file_path)?;
+        parse_lockfile(&lock_file_path, workspace)?
+    };
 
+    Ok(lock_file)
+}
+
+/// Returns the packages in the resolve that are from the specified registry
+/// and are needed by the selected workspace members
+pub fn registry_packages_in_resolve<'cfg>(
+    config: &CargoConfig,
+    args: &CargoSideloadCommonArgs,
+    resolve_args: &CargoSideloadResolveArgs,
+    workspace: &Workspace<'cfg>,
+    lock_file: &Resolve,
+) -> anyhow::Result<Vec<PackageId>> {
     let registry_index_url = registry_index_url(config, &args.registry)?;
 
+    // Cargo only applies these to the current package, so they'd be ignored at the root of a virtual workspace
+    if workspace.is_virtual() {
+        let flags = [
+            ("features", !resolve_args.features.is_empty()),
+            ("no-default-features", resolve_args.no_default_features),
+        ];
+        for (flag, is_used) in flags.iter() {
+            if *is_used {
+                anyhow::bail!(
+                    "--{} is not allowed in the root of a virtual workspace. Use --manifest-path with the manifest of a member instead.",
+                    flag
+                );
+            }
+        }
+    }
+
+    let members = selected_members(args, workspace)?;
+    let dependencies: HashSet<PackageId> = if resolve_args.is_narrowed() {
+        // Resolve again with only the requested features and target, keeping the locked versions
+        resolved_packages(
+            config,
+            &args.registry,
+            workspace,
+            &members,
+            Some(lock_file),
+            &resolve_opts(resolve_args),
+            resolve_args.target.as_deref(),
+        )?
+        .into_iter()
+        .collect()
+    } else {
+        reachable_packages(lock_file, &members)
+    };
+
     let mut packages = Vec::new();
 
     for package_id in lock_file.iter() {
+        if !dependencies.contains(&package_id) {
+            continue;
+        }
+
         let name = package_id.name().to_string();
         if let Some(packages) = &args.packages {
             if !packages.contains(&name) {
@@ -88,6 +745,213 @@ pub fn workspace_packages<'cfg>(
     Ok(packages)
 }
 
+/// Opens the workspace that contains the manifest from `--manifest-path` or `--path`
+pub fn workspace<'cfg>(
+    config: &'cfg CargoConfig,
+    args: &CargoSideloadCommonArgs,
+) -> anyhow::Result<Workspace<'cfg>> {
+    let manifest_path = canonicalize(args.manifest_path())?;
+    Workspace::new(&manifest_path, config)
+}
+
+/// Returns the workspace members selected by `--workspace` and `--member`.
+/// If neither is used, every member is selected when running from the workspace root,
+/// otherwise only the current package is selected.
+pub fn selected_members<'cfg>(
+    args: &CargoSideloadCommonArgs,
+    workspace: &Workspace<'cfg>,
+) -> anyhow::Result<Vec<PackageId>> {
+    let all_members = || {
+        workspace
+            .members()
+            .map(|member| member.package_id())
+            .collect()
+    };
+
+    if args.workspace {
+        return Ok(all_members());
+    }
+
+    if !args.members.is_empty() {
+        let mut members = Vec::new();
+        for name in &args.members {
+            match workspace
+                .members()
+                .find(|member| member.name().as_str() == name)
+            {
+                Some(member) => members.push(member.package_id()),
+                None => anyhow::bail!("Package \"{}\" is not a member of the workspace", name),
+            }
+        }
+        return Ok(members);
+    }
+
+    match workspace.current_opt() {
+        Some(current) if current.root() != workspace.root() => Ok(vec![current.package_id()]),
+        _ => Ok(all_members()),
+    }
+}
+
+/// The path from a root package to each of its dependencies, starting with the root and ending with the dependency
+pub type DependencyPaths = HashMap<PackageId, Vec<PackageId>>;
+
+/// Returns the shortest path from the root to every package it depends on, either directly or through other packages
+pub fn dependency_paths(resolve: &Resolve, root: PackageId) -> DependencyPaths {
+    let mut paths = DependencyPaths::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(vec![root]);
+
+    while let Some(path) = queue.pop_front() {
+        let package_id = path[path.len() - 1];
+        for (dep_id, _) in resolve.deps(package_id) {
+            if dep_id == root || paths.contains_key(&dep_id) {
+                continue;
+            }
+
+            let mut dep_path = path.clone();
+            dep_path.push(dep_id);
+            paths.insert(dep_id, dep_path.clone());
+            queue.push_back(dep_path);
+        }
+    }
+
+    paths
+}
+
+/// Returns every package in the resolve that the roots depend on, including the roots themselves
+fn reachable_packages(resolve: &Resolve, roots: &[PackageId]) -> HashSet<PackageId> {
+    let mut queue = roots.to_vec();
+    let mut reachable = HashSet::new();
+
+    while let Some(package_id) = queue.pop() {
+        if reachable.insert(package_id) {
+            queue.extend(resolve.deps(package_id).map(|(dep_id, _)| dep_id));
+        }
+    }
+
+    reachable
+}
+
+/// Unpacks a `.crate` file into the directory. The files are placed in a `[crate-name]-[version]` directory inside of it.
+pub fn unpack_crate(crate_file: &Path, directory: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::open(crate_file)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    archive.unpack(directory)?;
+    Ok(())
+}
+
+/// Unpacks a `.crate` file directly into the directory, without the `[crate-name]-[version]` directory
+/// that the files are in inside of the archive
+pub fn extract_crate(crate_file: &Path, directory: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::open(crate_file)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // Only plain relative paths are allowed so nothing can be written outside of the directory
+        let relative: PathBuf = path.components().skip(1).collect();
+        if path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            anyhow::bail!("Invalid path in crate file: {}", path.display());
+        }
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            anyhow::bail!("Links aren't supported in crate files: {}", path.display());
+        }
+
+        let target = directory.join(relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&target)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the `Cargo.toml` file of a packaged crate
+pub fn crate_manifest(crate_file: &Path) -> anyhow::Result<String> {
+    let file = std::fs::File::open(crate_file)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if path.components().count() == 2 && path.ends_with("Cargo.toml") {
+            let mut manifest = String::new();
+            entry.read_to_string(&mut manifest)?;
+            return Ok(manifest);
+        }
+    }
+
+    anyhow::bail!("{} has no Cargo.toml", crate_file.display())
+}
+
+/// Returns the files in a `.crate` file that are missing from the directory or have different contents.
+/// The directory is expected to have the same layout as the archive without its top-level directory,
+/// like the sources that Cargo unpacks. Files that are only in the directory are ignored.
+pub fn modified_files(crate_file: &Path, directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let file = std::fs::File::open(crate_file)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+
+    let mut modified = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let relative: PathBuf = entry.path()?.components().skip(1).collect();
+        let mut expected = Vec::new();
+        entry.read_to_end(&mut expected)?;
+
+        match std::fs::read(directory.join(&relative)) {
+            Ok(contents) if contents == expected => (),
+            Ok(_) => modified.push(relative),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => modified.push(relative),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(modified)
+}
+
+/// Returns the SHA-256 checksum of the file as a hex string, the same format as the registry index
+pub fn file_checksum(path: &Path) -> anyhow::Result<String> {
+    Ok(cargo::util::Sha256::new().update_path(path)?.finish_hex())
+}
+
+/// Prints rows in columns that are padded to the width of their longest value
+pub fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|name| name.len()).collect();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.len());
+        }
+    }
+
+    let print_row = |values: Vec<&str>| {
+        let columns: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect();
+        println!("{}", columns.join("  ").trim_end());
+    };
+
+    print_row(header.to_vec());
+    for row in rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
 /// Returns the name of the registry's directory in the local cache.
 /// The result is in the format `[registry_name]-[hash]`
 /// This function is copy/pasted from a private function in Cargo.
@@ -97,6 +961,37 @@ pub fn registry_directory(id: SourceId) -> String {
     format!("{}-{}", ident, hash)
 }
 
+/// Returns the url that a package will be downloaded from.
+/// This function is adapted from a private function in Cargo.
+pub fn download_url(registry_config: &RegistryConfig, package_id: PackageId) -> String {
+    let mut url = registry_config.dl.clone();
+    if !url.contains("{crate}")
+        && !url.contains("{version}")
+        && !url.contains("{prefix}")
+        && !url.contains("{lowerprefix}")
+    {
+        url.push_str("/{crate}/{version}/download");
+    }
+
+    replace_url_markers(&url, package_id)
+}
+
+/// Creates the url of a package's signature from a template with the same markers as the `dl` url,
+/// plus `{url}` for the package's download url
+pub fn signature_url(template: &str, download_url: &str, package_id: PackageId) -> String {
+    replace_url_markers(&template.replace("{url}", download_url), package_id)
+}
+
+fn replace_url_markers(url: &str, package_id: PackageId) -> String {
+    let name = package_id.name();
+    let prefix = index_prefix(&name);
+
+    url.replace("{crate}", &name)
+        .replace("{version}", &package_id.version().to_string())
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+}
+
 fn registry_index_url(config: &CargoConfig, registry_name: &str) -> anyhow::Result<String> {
     if registry_name == CRATES_IO_REGISTRY {
         return Ok(CRATES_IO_INDEX.to_owned());
@@ -113,6 +1008,64 @@ fn registry_index_url(config: &CargoConfig, registry_name: &str) -> anyhow::Resu
     }
 }
 
+/// A package from a registry in a `Cargo.lock` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: Version,
+    pub checksum: Option<String>,
+}
+
+/// Reads the packages from the registry in a `Cargo.lock` file without loading its workspace
+pub fn lockfile_packages(path: &Path, source_id: SourceId) -> anyhow::Result<Vec<LockedPackage>> {
+    let contents = std::fs::read_to_string(path)?;
+    locked_packages(&contents, source_id.url().as_str())
+}
+
+fn locked_packages(lockfile: &str, registry_url: &str) -> anyhow::Result<Vec<LockedPackage>> {
+    let lockfile: toml::Value = toml::from_str(lockfile)?;
+    let source = format!("registry+{}", registry_url);
+
+    // Older lockfiles keep the checksums in a separate `[metadata]` table
+    let metadata = lockfile.get("metadata").and_then(toml::Value::as_table);
+
+    let mut packages = Vec::new();
+    let entries = lockfile
+        .get("package")
+        .and_then(toml::Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for entry in entries {
+        if entry.get("source").and_then(toml::Value::as_str) != Some(source.as_str()) {
+            continue;
+        }
+
+        let name = entry.get("name").and_then(toml::Value::as_str);
+        let version = entry.get("version").and_then(toml::Value::as_str);
+        let (name, version) = match (name, version) {
+            (Some(name), Some(version)) => (name, version),
+            _ => continue,
+        };
+
+        let checksum = entry
+            .get("checksum")
+            .or_else(|| {
+                let key = format!("checksum {} {} ({})", name, version, source);
+                metadata.and_then(|metadata| metadata.get(&key))
+            })
+            .and_then(toml::Value::as_str)
+            .map(str::to_string);
+
+        packages.push(LockedPackage {
+            name: name.to_string(),
+            version: Version::parse(version)?,
+            checksum,
+        });
+    }
+
+    Ok(packages)
+}
+
 fn parse_lockfile<'cfg, P: AsRef<Path>>(
     path: P,
     workspace: &Workspace<'cfg>,
@@ -122,5 +1075,386 @@ fn parse_lockfile<'cfg, P: AsRef<Path>>(
         cargo::util::toml::parse(&toml_string, path.as_ref(), workspace.config())?;
 
     let encodable_resolve: EncodableResolve = toml.try_into()?;
-    Ok(encodable_resolve.into_resolve(&toml_string, workspace)?)
+    encodable_resolve.into_resolve(&toml_string, workspace)
+}
+
+/// A version of a package in the local package cache
+pub struct CachedCrate {
+    pub name: String,
+    pub version: Version,
+    pub crate_file: PathBuf,
+    pub source_dir: PathBuf,
+    pub modified: SystemTime,
+}
+
+impl CachedCrate {
+    /// Size of the `.crate` file and the unpacked source
+    pub fn size(&self) -> anyhow::Result<(u64, Option<u64>)> {
+        let crate_size = fs::metadata(&self.crate_file)?.len();
+        let source_size = if self.source_dir.is_dir() {
+            Some(directory_size(&self.source_dir)?)
+        } else {
+            None
+        };
+
+        Ok((crate_size, source_size))
+    }
+
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.modified)
+            .unwrap_or_default()
+    }
+
+    /// Deletes the `.crate` file and the unpacked source. Returns the number of bytes that were freed.
+    pub fn remove(&self) -> anyhow::Result<u64> {
+        let (crate_size, source_size) = self.size()?;
+
+        fs::remove_file(&self.crate_file)?;
+        if source_size.is_some() {
+            fs::remove_dir_all(&self.source_dir)?;
+        }
+
+        Ok(crate_size + source_size.unwrap_or(0))
+    }
+}
+
+/// Returns every version in the registry's package cache, sorted by name and version
+pub fn cached_crates(
+    config: &CargoConfig,
+    source_id: SourceId,
+) -> anyhow::Result<Vec<CachedCrate>> {
+    let registry_directory = registry_directory(source_id);
+    let cache_dir = config
+        .registry_cache_path()
+        .join(&registry_directory)
+        .into_path_unlocked();
+    let source_dir = config
+        .registry_source_path()
+        .join(&registry_directory)
+        .into_path_unlocked();
+
+    let entries = match fs::read_dir(&cache_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut cached_crates = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let stem = match file_name.strip_suffix(".crate") {
+            Some(stem) => stem,
+            None => continue,
+        };
+
+        if let Some((name, version)) = parse_crate_file_name(stem) {
+            cached_crates.push(CachedCrate {
+                name,
+                version,
+                crate_file: entry.path(),
+                source_dir: source_dir.join(stem),
+                modified: entry.metadata()?.modified()?,
+            });
+        }
+    }
+
+    cached_crates.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+    Ok(cached_crates)
+}
+
+/// Splits a `[crate-name]-[version]` file name into the crate name and version.
+/// Both crate names and versions can contain dashes, so the version starts at the first dash that's followed by a valid version.
+fn parse_crate_file_name(stem: &str) -> Option<(String, Version)> {
+    stem.match_indices('-').find_map(|(index, _)| {
+        let version = Version::parse(&stem[index + 1..]).ok()?;
+        Some((stem[..index].to_string(), version))
+    })
+}
+
+fn directory_size(path: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_update_candidate() {
+        let version = |v: &str| Version::parse(v).unwrap();
+
+        assert!(is_update_candidate(
+            &version("1.1.0"),
+            &version("1.0.0"),
+            false
+        ));
+        assert!(!is_update_candidate(
+            &version("2.0.0-alpha.1"),
+            &version("1.0.0"),
+            false
+        ));
+        assert!(is_update_candidate(
+            &version("2.0.0-alpha.1"),
+            &version("1.0.0"),
+            true
+        ));
+
+        // Staying on a pre-release train
+        assert!(is_update_candidate(
+            &version("1.0.0-beta.5"),
+            &version("1.0.0-beta.2"),
+            false
+        ));
+        assert!(is_update_candidate(
+            &version("1.0.0"),
+            &version("1.0.0-beta.2"),
+            false
+        ));
+        assert!(!is_update_candidate(
+            &version("1.1.0-alpha.1"),
+            &version("1.0.0-beta.2"),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_locked_packages() {
+        let lockfile = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["my_lib"]
+
+[[package]]
+name = "my_lib"
+version = "0.1.1"
+source = "registry+file:///tmp/reg/index"
+checksum = "abc"
+
+[[package]]
+name = "other_lib"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "def"
+"#;
+        let old_lockfile = r#"
+[[package]]
+name = "my_lib"
+version = "0.1.1"
+source = "registry+file:///tmp/reg/index"
+
+[metadata]
+"checksum my_lib 0.1.1 (registry+file:///tmp/reg/index)" = "abc"
+"#;
+
+        let expected = vec![LockedPackage {
+            name: "my_lib".to_string(),
+            version: Version::parse("0.1.1").unwrap(),
+            checksum: Some("abc".to_string()),
+        }];
+
+        assert_eq!(
+            locked_packages(lockfile, "file:///tmp/reg/index").unwrap(),
+            expected
+        );
+        assert_eq!(
+            locked_packages(old_lockfile, "file:///tmp/reg/index").unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_nearest_version() {
+        let config = CargoConfig::default().unwrap();
+        let source_id = SourceId::crates_io(&config).unwrap();
+        let summaries: Vec<Summary> = ["0.1.0", "0.1.2", "0.1.3", "0.2.0"]
+            .iter()
+            .map(|version| {
+                let package_id = PackageId::new("my_lib", *version, source_id).unwrap();
+                Summary::new(
+                    &config,
+                    package_id,
+                    vec![],
+                    &Default::default(),
+                    None::<String>,
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let version = |v: &str| Version::parse(v).unwrap();
+        let req = |r: &str| VersionReq::parse(r).unwrap();
+        let nearest = |reqs: &[VersionReq], current: &str| {
+            nearest_version(&summaries, reqs, &version(current))
+                .map(|summary| summary.version().to_string())
+        };
+
+        assert_eq!(nearest(&[req("0.1")], "0.1.1"), Some("0.1.2".to_string()));
+        assert_eq!(
+            nearest(&[req("0.1"), req("<0.1.2")], "0.1.1"),
+            Some("0.1.0".to_string())
+        );
+        assert_eq!(nearest(&[req("=0.1.1")], "0.1.1"), None);
+    }
+
+    #[test]
+    fn test_matching_version() {
+        let config = CargoConfig::default().unwrap();
+        let source_id = SourceId::crates_io(&config).unwrap();
+        let summaries: Vec<Summary> = ["1.0.0", "1.1.0", "2.0.0-beta.1"]
+            .iter()
+            .map(|version| {
+                let package_id = PackageId::new("my_lib", *version, source_id).unwrap();
+                Summary::new(
+                    &config,
+                    package_id,
+                    vec![],
+                    &Default::default(),
+                    None::<String>,
+                )
+                .unwrap()
+            })
+            .collect();
+        let matching = |spec: &str| {
+            let spec: CrateSpec = spec.parse().unwrap();
+            matching_version(&summaries, &spec.req).map(|summary| summary.version().to_string())
+        };
+
+        // A crate without a version is the latest release, not the latest pre-release
+        assert_eq!(matching("my_lib"), Some("1.1.0".to_string()));
+        assert_eq!(matching("my_lib@1.0.0"), Some("1.0.0".to_string()));
+        assert_eq!(
+            matching("my_lib@2.0.0-beta.1"),
+            Some("2.0.0-beta.1".to_string())
+        );
+        assert_eq!(matching("my_lib@1.2.0"), None);
+    }
+
+    #[test]
+    fn test_local_index_package_names() {
+        let directory = tempfile::tempdir().unwrap();
+        let repo = git2::Repository::init(directory.path()).unwrap();
+
+        let files = [
+            ("config.json", "{}"),
+            (".github/workflows/ci.yml", "on: push"),
+            ("1/a", r#"{"name":"a","vers":"0.1.0"}"#),
+            ("3/a/abc", r#"{"name":"abc","vers":"0.1.0"}"#),
+            ("my/_l/my_lib", r#"{"name":"my_lib","vers":"0.1.0"}"#),
+            ("my/_l/README.md", "not a package"),
+        ];
+        let mut index = repo.index().unwrap();
+        for (path, contents) in files.iter() {
+            let full_path = directory.path().join(path);
+            std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            std::fs::write(&full_path, contents).unwrap();
+            index.add_path(Path::new(path)).unwrap();
+        }
+        let tree_id = index.write_tree().unwrap();
+        let signature = git2::Signature::now("test", "test@localhost").unwrap();
+        repo.commit(
+            Some("refs/remotes/origin/master"),
+            &signature,
+            &signature,
+            "Index",
+            &repo.find_tree(tree_id).unwrap(),
+            &[],
+        )
+        .unwrap();
+
+        let local_index = LocalIndex::new(repo).unwrap();
+        assert_eq!(
+            local_index.package_names().unwrap(),
+            vec!["a".to_string(), "abc".to_string(), "my_lib".to_string()]
+        );
+        assert_eq!(local_index.entries("My_Lib").unwrap()[0]["vers"], "0.1.0");
+        assert!(local_index.entries("other_lib").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_modified_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let crate_file = directory.path().join("my_lib-0.1.0.crate");
+
+        let encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&crate_file).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for (path, contents) in &[
+            ("my_lib-0.1.0/Cargo.toml", "[package]"),
+            ("my_lib-0.1.0/src/lib.rs", "pub fn f() {}"),
+            ("my_lib-0.1.0/src/extra.rs", "pub fn g() {}"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let source = directory.path().join("my_lib-0.1.0");
+        unpack_crate(&crate_file, directory.path()).unwrap();
+        assert!(modified_files(&crate_file, &source).unwrap().is_empty());
+
+        std::fs::write(source.join("src/lib.rs"), "pub fn changed() {}").unwrap();
+        std::fs::remove_file(source.join("src/extra.rs")).unwrap();
+        std::fs::write(source.join(".cargo-ok"), "").unwrap();
+        assert_eq!(
+            modified_files(&crate_file, &source).unwrap(),
+            vec![PathBuf::from("src/lib.rs"), PathBuf::from("src/extra.rs")]
+        );
+    }
+
+    #[test]
+    fn test_index_paths() {
+        assert_eq!(index_file_path("a"), "1/a");
+        assert_eq!(index_file_path("ab"), "2/ab");
+        assert_eq!(index_file_path("abc"), "3/a/abc");
+        assert_eq!(index_file_path("My_Lib"), "my/_l/my_lib");
+
+        let config = CargoConfig::default().unwrap();
+        let source_id = SourceId::crates_io(&config).unwrap();
+        let package_id = PackageId::new("My_Lib", "0.1.0", source_id).unwrap();
+        assert_eq!(
+            replace_url_markers(
+                "https://example.com/{prefix}/{lowerprefix}/{crate}-{version}.crate",
+                package_id
+            ),
+            "https://example.com/My/_L/my/_l/My_Lib-0.1.0.crate"
+        );
+    }
+
+    #[test]
+    fn test_parse_crate_file_name() {
+        let parsed = |stem: &str| {
+            parse_crate_file_name(stem).map(|(name, version)| (name, version.to_string()))
+        };
+
+        assert_eq!(
+            parsed("my_lib-0.1.0"),
+            Some(("my_lib".to_string(), "0.1.0".to_string()))
+        );
+        assert_eq!(
+            parsed("my-lib-2-1.0.0-beta.1"),
+            Some(("my-lib-2".to_string(), "1.0.0-beta.1".to_string()))
+        );
+        assert_eq!(parsed("my_lib"), None);
+    }
 }